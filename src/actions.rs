@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::channel;
+
+use dialoguer::console::Key;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::dialogue::dialogue_ui::{Dialogue, DialogueItem, DialogueMessage};
+use crate::shell::{quote_powershell, split_command};
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const CTRL_E: Key = Key::Char('\u{5}');
+const CTRL_O: Key = Key::Char('\u{f}');
+const CTRL_S: Key = Key::Char('\u{13}');
+const CTRL_T: Key = Key::Char('\u{14}');
+const CTRL_Y: Key = Key::Char('\u{19}');
+
+/// What to do with the chosen directory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Cd,
+    NewTab,
+    SplitPane,
+    Editor,
+    CopyPath,
+}
+
+const ALL_ACTIONS: [Action; 5] = [Action::Cd, Action::NewTab, Action::SplitPane, Action::Editor, Action::CopyPath];
+
+impl Action {
+    fn key(&self) -> Option<Key> {
+        match self {
+            Action::Cd => None,
+            Action::NewTab => Some(CTRL_T),
+            Action::SplitPane => Some(CTRL_S),
+            Action::Editor => Some(CTRL_E),
+            Action::CopyPath => Some(CTRL_Y),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Action::Cd => "Change directory",
+            Action::NewTab => "Open in a new tab (Ctrl+T)",
+            Action::SplitPane => "Open in a split pane (Ctrl+S)",
+            Action::Editor => "Open in the editor (Ctrl+E)",
+            Action::CopyPath => "Copy the path (Ctrl+Y)",
+        }
+    }
+}
+
+/// The [actions] section of the config, e.g.
+/// ```toml
+/// [actions]
+/// editor = "code"
+///
+/// [actions.languages.csharp]
+/// default_action = "editor"
+/// editor = "rider64"
+/// ```
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct ActionSettings {
+    /// Used when the language has no editor of its own, falls back to $EDITOR and then to VS Code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    editor: Option<String>,
+    /// Keyed by the language tag, e.g. rust or js
+    languages: HashMap<String, LanguageActions>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct LanguageActions {
+    /// What Enter does for projects in this language
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_action: Option<Action>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    editor: Option<String>,
+}
+
+impl ActionSettings {
+    fn default_action(&self, languages: &[&str]) -> Option<Action> {
+        return languages.iter()
+            .find_map(|l| self.languages.get(*l).and_then(|a| a.default_action));
+    }
+
+    fn editor(&self, languages: &[&str]) -> String {
+        let language_editor = languages.iter()
+            .find_map(|l| self.languages.get(*l).and_then(|a| a.editor.clone()));
+
+        return language_editor
+            .or_else(|| self.editor.clone())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .unwrap_or("code".to_string());
+    }
+}
+
+pub fn load_settings() -> &'static ActionSettings {
+    return &config::get().actions;
+}
+
+/// Keys that choose an item in the picker together with an action.
+pub fn confirm_keys() -> Vec<Key> {
+    let mut keys = ALL_ACTIONS.iter().filter_map(|a| a.key()).collect::<Vec<_>>();
+    keys.push(CTRL_O);
+    return keys;
+}
+
+/// Maps the key that confirmed the selection to an action, asking the user when the action menu was requested.
+/// Enter does the requested action, or the default one for the language, or cd.
+pub fn action_for_key(key: &Key, requested: Option<Action>, settings: &ActionSettings, languages: &[&str]) -> Result<Option<Action>> {
+    if *key == CTRL_O {
+        return choose_action();
+    }
+
+    if let Some(action) = ALL_ACTIONS.iter().find(|a| a.key().as_ref() == Some(key)) {
+        return Ok(Some(*action));
+    }
+
+    let action = requested
+        .or_else(|| settings.default_action(languages))
+        .unwrap_or(Action::Cd);
+    return Ok(Some(action));
+}
+
+fn choose_action() -> Result<Option<Action>> {
+    let (tx, rx) = channel::<DialogueMessage<ActionItem>>();
+    tx.send(DialogueMessage::Finish)?;
+
+    let mut dialogue = Dialogue::new(rx);
+    dialogue.add_items(ALL_ACTIONS.iter().map(|a| ActionItem(*a)).collect());
+    let selection = dialogue.prompt("Select action").interact()?;
+    return Ok(selection.first().map(|s| s.0));
+}
+
+pub fn run(action: Action, directory: &str, settings: &ActionSettings, languages: &[&str]) -> Result<()> {
+    if let Some(command) = command(action, directory, settings, languages)? {
+        print!("<#Execute#>{command}");
+    }
+
+    return Ok(());
+}
+
+/// The powershell command that does the action, None for the actions that are done right away.
+pub fn command(action: Action, directory: &str, settings: &ActionSettings, languages: &[&str]) -> Result<Option<String>> {
+    let command = match action {
+        Action::Cd => format!("cd {}", quote_powershell(directory)),
+        Action::NewTab => format!("{} nt -d {}", config::get().launcher.wt, quote_powershell(directory)),
+        Action::SplitPane => format!("{} sp -d {}", config::get().launcher.wt, quote_powershell(directory)),
+        Action::Editor => editor_command(&settings.editor(languages), directory),
+        Action::CopyPath => {
+            copy_to_clipboard(directory)?;
+            return Ok(None);
+        }
+    };
+
+    return Ok(Some(command));
+}
+
+/// Runs the editor with the call operator, so that a program path with spaces and arguments like `code -n` both work.
+fn editor_command(editor: &str, directory: &str) -> String {
+    // A path to the program with spaces in it, that was not quoted in the config
+    let parts = if Path::new(editor).is_file() { vec![editor.to_string()] } else { split_command(editor) };
+
+    let arguments = parts.iter().map(|p| quote_powershell(p)).chain([quote_powershell(directory)]).collect::<Vec<_>>();
+    return format!("& {}", arguments.join(" "));
+}
+
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    // OSC 52 asks the terminal to set the clipboard. Stdout is read by the shell wrapper, so this goes to stderr.
+    let mut stderr = std::io::stderr();
+    write!(stderr, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stderr.flush()?;
+    return Ok(());
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+
+    return result;
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct ActionItem(Action);
+
+impl Display for ActionItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0.description())
+    }
+}
+
+impl DialogueItem for ActionItem {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_command_quotes_every_part() {
+        assert_eq!(editor_command("code -n", r"C:\work\api"), r"& 'code' '-n' 'C:\work\api'");
+        assert_eq!(editor_command(r#""C:\Program Files\Code.exe" -n"#, r"C:\it's"),
+                   r"& 'C:\Program Files\Code.exe' '-n' 'C:\it''s'");
+    }
+
+    #[test]
+    fn unquoted_editor_path_with_spaces() {
+        let folder = std::env::temp_dir().join(format!("wts editor {}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let editor = folder.join("my editor.exe");
+        std::fs::write(&editor, "").unwrap();
+        let editor = editor.to_str().unwrap();
+
+        assert_eq!(editor_command(editor, "api"), format!("& {} 'api'", quote_powershell(editor)));
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
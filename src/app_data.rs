@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const APP_NAME: &str = "wterm-sessionizer";
+
+pub fn app_folder() -> PathBuf {
+    if let Some(data_folder) = &config::get().data_folder {
+        return data_folder.clone();
+    }
+
+    return dirs::data_dir().unwrap().join(APP_NAME);
+}
+
+/// Reads a json file from the app folder, falling back to the default value when the file does not exist yet.
+pub fn read_json<T>(file_name: &str) -> Result<T> where T: DeserializeOwned + Default {
+    let path = app_folder().join(file_name);
+    if !path.exists() {
+        return Ok(T::default());
+    }
+
+    let file = std::fs::OpenOptions::new().read(true).open(path)?;
+    let data = serde_json::from_reader::<_, T>(&file)?;
+    return Ok(data);
+}
+
+pub fn is_same_path(left: &str, right: &str) -> bool {
+    return path_key(left) == path_key(right);
+}
+
+/// The same for every spelling of a path, for looking paths up in sets and maps.
+pub fn path_key(path: &str) -> String {
+    // Windows paths are case-insensitive
+    return path.trim_end_matches(['\\', '/']).to_lowercase();
+}
+
+pub fn write_json<T>(file_name: &str, data: &T) -> Result<()> where T: Serialize {
+    let app_folder = app_folder();
+    if !app_folder.exists() {
+        std::fs::create_dir_all(&app_folder)?;
+    }
+
+    let file = std::fs::File::create(app_folder.join(file_name))?;
+    serde_json::to_writer(&file, data)?;
+    return Ok(());
+}
@@ -1,85 +1,388 @@
-use std::{io::Seek, sync::mpsc::channel};
+use std::collections::{HashSet, VecDeque};
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR_STR};
+use std::sync::mpsc::channel;
 
-use dirs;
-use serde::{Deserialize, Serialize};
-use serde_json;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use path_absolutize::Absolutize;
 
-use crate::dialogue::dialogue_ui::{Dialogue, DialogueMessage};
+use crate::app_data::path_key;
+use crate::config;
+use crate::dialogue::dialogue_ui::DialogueMessage;
+use crate::favorites;
+use crate::picker;
+use crate::picker::Picker;
+use crate::project_file;
+use crate::history;
+use crate::session;
+use crate::visit_log;
 
 type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
 
-pub fn expand(path: &str) -> Result<()> {
-    let default_cd_location = path.to_string();
+pub fn expand(keywords: &[String], session: Option<&str>, picker: Picker) -> Result<()> {
+    if let Some(steps) = back_steps(keywords) {
+        return go_back(session, steps);
+    }
+
+    if let Some(first) = keywords.first().and_then(|k| k.strip_prefix('@')) {
+        let mut keywords = keywords.to_vec();
+        keywords[0] = first.to_string();
+        return expand_here(&keywords, picker);
+    }
 
-    let target_path = std::env::current_dir()?.join(path);
+    let path = expand_path(&keywords.join(" "));
+    let default_cd_location = path.clone();
+
+    let target_path = std::env::current_dir()?.join(&path);
     if target_path.exists() {
         execute_cd(&default_cd_location);
         return Ok(());
     }
 
-    if let Ok(folders) = find_expanded_folder(path) {
+    let abbreviated_folders = find_abbreviated_folders(&path)?;
+    if !abbreviated_folders.is_empty() && choose_folder(abbreviated_folders, &picker) {
+        return Ok(());
+    }
+
+    if let Ok(folders) = find_expanded_folder(keywords) {
         if folders.is_empty() {
             execute_cd(&default_cd_location);
             return Ok(());
         }
 
-        if folders.len() == 1 {
-            let folder = folders[0].clone();
-            execute_cd(&folder);
+        if choose_folder(folders, &picker) {
             return Ok(());
         }
+    }
+
+    execute_cd(&default_cd_location);
 
-        let (tx, rx) = channel::<DialogueMessage<String>>();
-        tx.send(DialogueMessage::ItemsFound(folders)).unwrap();
-        let selection = Dialogue::new(rx).prompt("Select folder").interact();
+    return Ok(());
+}
 
-        if let Ok(Some(selection)) = selection {
-            execute_cd(&selection);
-            return Ok(());
+/// Goes to the only folder, or lets the user choose one. False when the user did not choose anything.
+/// Folders marked in the picker are added to the favorites instead.
+fn choose_folder(folders: Vec<String>, picker: &Picker) -> bool {
+    if folders.len() == 1 {
+        execute_cd(&folders[0]);
+        return true;
+    }
+
+    let (tx, rx) = channel::<DialogueMessage<String>>();
+    tx.send(DialogueMessage::Finish).unwrap();
+    let selection = picker::pick(picker, rx, folders, "Select folder", vec![], true);
+
+    let Ok(Some(selection)) = selection else {
+        return false;
+    };
+
+    if selection.marked.is_empty() {
+        execute_cd(&selection.item);
+        return true;
+    }
+
+    let mut pinned = 0;
+    for folder in &selection.marked {
+        match favorites::pin(folder, None) {
+            Ok(()) => pinned += 1,
+            Err(e) => eprintln!("Could not add {folder} to the favorites: {e}"),
         }
-        
     }
+    println!("Added {pinned} directories to the favorites");
+    return true;
+}
 
-    execute_cd(&default_cd_location);
+/// Expands `~` and environment variables, and `...` to `../..` with one more level for every extra dot.
+fn expand_path(path: &str) -> String {
+    let expanded = shellexpand::full(path).map(|p| p.into_owned()).unwrap_or(path.to_string());
+
+    return expanded.split(['\\', '/'])
+        .map(|segment| {
+            if segment.len() > 2 && segment.chars().all(|c| c == '.') {
+                vec![".."; segment.len() - 1].join(MAIN_SEPARATOR_STR)
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(MAIN_SEPARATOR_STR);
+}
+
+/// Folders on disk for an abbreviated path like `s/fr/b`, where every segment is the start of a folder name,
+/// e.g. `src/frontend/backend`. A segment that names a folder exactly is not treated as an abbreviation.
+fn find_abbreviated_folders(path: &str) -> Result<Vec<String>> {
+    // A single keyword is a search in the history, not an abbreviation
+    if !path.contains(['\\', '/']) {
+        return Ok(vec![]);
+    }
+
+    let mut candidates = vec![std::env::current_dir()?];
+    for component in Path::new(path).components() {
+        candidates = match component {
+            Component::Normal(segment) => {
+                let segment = segment.to_string_lossy();
+                candidates.iter().flat_map(|c| folders_starting_with(c, &segment)).collect()
+            }
+            // Drives, roots and dots are taken as they are
+            _ => candidates.iter().map(|c| c.join(component.as_os_str())).collect(),
+        };
+
+        if candidates.is_empty() {
+            break;
+        }
+    }
+
+    let folders = candidates.iter()
+        .map(|c| c.absolutize().map(|p| p.to_string_lossy().to_string()).unwrap_or(c.to_string_lossy().to_string()))
+        .collect();
+    return Ok(folders);
+}
+
+fn folders_starting_with(parent: &Path, prefix: &str) -> Vec<PathBuf> {
+    let exact = parent.join(prefix);
+    if exact.is_dir() {
+        return vec![exact];
+    }
 
+    let prefix = prefix.to_lowercase();
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return vec![];
+    };
+
+    let mut folders = entries.flatten()
+        .filter(|e| e.file_name().to_string_lossy().to_lowercase().starts_with(&prefix))
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect::<Vec<_>>();
+    folders.sort();
+    return folders;
+}
+
+/// Like `expand`, but only goes to folders inside of the git repository or project of the current directory.
+pub fn expand_here(keywords: &[String], picker: Picker) -> Result<()> {
+    let folders = find_folders_here(keywords)?;
+    if folders.is_empty() {
+        return Err(format!("No folders in this project match '{}'", keywords.join(" ")).into());
+    }
+
+    choose_folder(folders, &picker);
     return Ok(());
 }
 
+/// Folders inside of the git repository or project of the current directory that match the keywords,
+/// from the history and from a shallow scan of the project, so that other projects never come up.
+fn find_folders_here(keywords: &[String]) -> Result<Vec<String>> {
+    // Unvisited folders are ranked like ones that were visited once, long ago
+    const UNVISITED_FRECENCY: f64 = 0.25;
+
+    let current_dir = std::env::current_dir()?;
+    let Some(root) = project_file::find_root(&current_dir, true) else {
+        return Err(format!("{} is not inside of a git repository or a project with {}", current_dir.display(), project_file::FILE_NAME).into());
+    };
+    let root = root.to_string_lossy().to_string();
+
+    let now = history::now_timestamp()?;
+    let mut candidates = history::load()?.visited_dirs.into_iter()
+        .map(|d| {
+            let frecency = d.frecency(now);
+            (d.dir, frecency)
+        })
+        .collect::<Vec<_>>();
+    let mut known_dirs = candidates.iter().map(|(d, _)| path_key(d)).collect::<HashSet<_>>();
+    for dir in scan_folders(Path::new(&root)) {
+        let dir = dir.to_string_lossy().to_string();
+        if known_dirs.insert(path_key(&dir)) {
+            candidates.push((dir, UNVISITED_FRECENCY));
+        }
+    }
+
+    let keywords = split_keywords(keywords);
+    let matcher = SkimMatcherV2::default().smart_case();
+    let mut matches = candidates.into_iter()
+        .filter_map(|(dir, frecency)| {
+            let relative = relative_segments(&dir, &root)?;
+            let score = if keywords.is_empty() { 1 } else { match_segments(&relative.join("/"), &keywords, &matcher)? };
+            Some((frecency * score as f64, dir))
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+    return Ok(matches.into_iter().map(|(_, dir)| dir).collect());
+}
+
+/// The segments of the directory below the root, None when it is not inside of the root.
+fn relative_segments<'a>(dir: &'a str, root: &str) -> Option<Vec<&'a str>> {
+    let segments = dir.split(['\\', '/']).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    let root_segments = root.split(['\\', '/']).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    if segments.len() < root_segments.len() {
+        return None;
+    }
+
+    let is_inside = segments.iter().zip(&root_segments).all(|(s, r)| s.to_lowercase() == r.to_lowercase());
+    return is_inside.then(|| segments[root_segments.len()..].to_vec());
+}
+
+/// Folders a few levels below the root, without hidden folders and the ones discovery skips.
+fn scan_folders(root: &Path) -> Vec<PathBuf> {
+    const MAX_DEPTH: usize = 3;
+    const MAX_FOLDERS: usize = 5000;
+
+    let mut folders = vec![];
+    let mut queue = VecDeque::from([(root.to_path_buf(), 0)]);
+    while let Some((dir, depth)) = queue.pop_front() {
+        if depth == MAX_DEPTH || folders.len() >= MAX_FOLDERS {
+            continue;
+        }
+
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            if !is_dir || name.starts_with('.') || config::get().discovery.skip.contains(&name) {
+                continue;
+            }
+
+            folders.push(entry.path());
+            queue.push_back((entry.path(), depth + 1));
+        }
+    }
+
+    return folders;
+}
+
+/// `-` goes one directory back, `-3` three of them.
+fn back_steps(keywords: &[String]) -> Option<usize> {
+    let [keyword] = keywords else {
+        return None;
+    };
+
+    let steps = keyword.strip_prefix('-')?;
+    if steps.is_empty() {
+        return Some(1);
+    }
+    return steps.parse::<usize>().ok().filter(|s| *s > 0);
+}
+
+fn go_back(session: Option<&str>, steps: usize) -> Result<()> {
+    let dirs = previous_dirs(session)?;
+    return match dirs.get(steps - 1) {
+        Some(dir) => {
+            execute_cd(dir);
+            Ok(())
+        }
+        None => Err(format!("This shell has only been in {} other directories", dirs.len()).into()),
+    };
+}
+
+/// Lets the user choose among the directories this shell was in, the latest first.
+pub fn pick_previous(session: Option<&str>, picker: Picker) -> Result<()> {
+    let dirs = previous_dirs(session)?;
+    if dirs.is_empty() {
+        return Err("This shell has not been in other directories yet".into());
+    }
+
+    let (tx, rx) = channel::<DialogueMessage<String>>();
+    tx.send(DialogueMessage::Finish)?;
+    if let Some(selection) = picker::pick(&picker, rx, dirs, "Select previous folder", vec![], false)? {
+        execute_cd(&selection.item);
+    }
+
+    return Ok(());
+}
+
+fn previous_dirs(session: Option<&str>) -> Result<Vec<String>> {
+    let Some(session) = session else {
+        return Err("Going back needs WTS_SESSION, which is set by the script from `wts init`".into());
+    };
+
+    let current_dir = std::env::current_dir()?;
+    return session::previous_dirs(session, &current_dir.to_string_lossy());
+}
+
 fn execute_cd(path: &str) {
     let cmd = format!("<#Execute#>cd \"{:}\"", path);
     println!("{}", cmd.to_string());
 }
 
-pub fn find_expanded_folder(path: &str) -> Result<Vec<String>> {
-    let file_name = "directory_history.json";
-    let app_name = "wterm-sessionizer";
-    let app_folder = dirs::data_dir().unwrap().join(app_name);
-
-    let settings = if !app_folder.exists() {
-        DirectoryHistory {
-            visited_dirs: Vec::new(),
-        }
-    } else {
-        let settings_path = app_folder.join(file_name);
+/// History directories whose path segments match the keywords in order, with the last keyword matching the last segment.
+/// Matching is fuzzy and smart-case, the best matches that are visited often come first.
+pub fn find_expanded_folder(keywords: &[String]) -> Result<Vec<String>> {
+    let keywords = split_keywords(keywords);
 
-        let file = std::fs::OpenOptions::new().read(true).open(settings_path)?;
-        let settings = serde_json::from_reader::<_, DirectoryHistory>(&file);
-        settings.unwrap_or(DirectoryHistory {
-            visited_dirs: Vec::new(),
+    let now = history::now_timestamp()?;
+    let matcher = SkimMatcherV2::default().smart_case();
+    let mut matches = history::load()?.visited_dirs.into_iter()
+        .filter_map(|d| {
+            let score = match_segments(&d.dir, &keywords, &matcher)?;
+            Some((d.frecency(now) * score as f64, d.dir))
         })
+        .collect::<Vec<_>>();
+
+    matches.sort_by(|a, b| b.0.total_cmp(&a.0));
+    return Ok(matches.into_iter().map(|(_, dir)| dir).collect());
+}
+
+/// A keyword like src/frontend stands for several segments.
+fn split_keywords(keywords: &[String]) -> Vec<&str> {
+    return keywords.iter()
+        .flat_map(|k| k.split(['\\', '/']))
+        .filter(|k| !k.is_empty())
+        .collect();
+}
+
+fn match_segments(dir: &str, keywords: &[&str], matcher: &SkimMatcherV2) -> Option<i64> {
+    let segments = dir.split(['\\', '/']).filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    let (last_keyword, keywords) = keywords.split_last()?;
+    let (basename, mut segments) = segments.split_last()?;
+
+    // The basename is what we usually mean, so it weighs more than the rest of the path
+    let mut score = 2 * matcher.fuzzy_match(basename, last_keyword)?;
+
+    // The other keywords go over the preceding segments in order, the closest matching segment is taken for each
+    for keyword in keywords.iter().rev() {
+        let (position, segment_score) = segments.iter().enumerate().rev()
+            .find_map(|(idx, segment)| matcher.fuzzy_match(segment, keyword).map(|score| (idx, score)))?;
+        score += segment_score;
+        segments = &segments[..position];
+    }
+
+    // Scores can be zero or less for odd matches, they still count as a match
+    return Some(score.max(1));
+}
+
+/// Prints the best matching history directories for completing `wcd <partial>` in the shell.
+pub fn complete(partial: &[String]) -> Result<()> {
+    const MAX_COMPLETIONS: usize = 10;
+
+    let dirs = if partial.iter().all(|p| p.trim().is_empty()) {
+        visited_dirs()?
+    } else {
+        find_expanded_folder(partial)?
     };
 
-    let mut dirs = settings.visited_dirs;
-    dirs.sort_by(|a, b| b.times.partial_cmp(&a.times).unwrap());
+    for dir in dirs.iter().take(MAX_COMPLETIONS) {
+        println!("{dir}");
+    }
+    return Ok(());
+}
+
+/// All the directories from the history, the ones with the highest frecency first.
+pub fn visited_dirs() -> Result<Vec<String>> {
+    let now = history::now_timestamp()?;
+    let mut dirs = history::load()?.visited_dirs;
+    dirs.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
 
-    let similar = dirs
-        .iter()
-        .filter(|d| d.dir.contains(&path))
-        .map(|d| d.dir.clone());
-    return Ok(similar.into_iter().collect());
+    return Ok(dirs.into_iter().map(|d| d.dir).collect());
 }
 
-pub fn on_changed_directory(new_dir: &str) -> Result<()> {
+pub fn on_changed_directory(new_dir: &str, session: Option<&str>) -> Result<()> {
+    // WTS_INCOGNITO=1 keeps the directories of this shell out of the history
+    if std::env::var("WTS_INCOGNITO").is_ok_and(|v| v == "1") {
+        return Ok(());
+    }
+
     // append current dir name
     let full_path = std::env::current_dir()?;
     let new_full_path = full_path.join(new_dir);
@@ -90,70 +393,134 @@ pub fn on_changed_directory(new_dir: &str) -> Result<()> {
 
     let new_full_path = new_full_path.to_str().unwrap().to_string();
 
-    let file_name = "directory_history.json";
-    let app_name = "wterm-sessionizer";
-    let app_folder = dirs::data_dir().unwrap().join(app_name);
+    // Going back should work for every directory, so the session keeps the excluded ones too
+    if let Some(session) = session {
+        session::push(session, &new_full_path)?;
+    }
 
-    if !app_folder.exists() {
-        std::fs::create_dir_all(&app_folder)?;
+    if history::Exclusions::load()?.is_excluded(&new_full_path) {
+        return Ok(());
     }
 
-    let settings_path = app_folder.join(file_name);
+    // The prompt hook runs on every directory change, so this only appends to the log.
+    // The log is folded into the history when it is read, or here once it gets too big.
+    visit_log::append(history::now_timestamp()?, &new_full_path)?;
+    if visit_log::is_over_limit() {
+        history::compact()?;
+    }
 
-    let mut file = std::fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(settings_path)?;
+    return Ok(());
+}
 
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs();
-    let mut existing_data =
-        serde_json::from_reader::<_, DirectoryHistory>(&file).unwrap_or(DirectoryHistory {
-            visited_dirs: Vec::new(),
-        });
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
 
-    let existing_dir = existing_data
-        .visited_dirs
-        .iter_mut()
-        .find(|d| d.dir == new_full_path);
+    use super::*;
+    use crate::hooks;
+    use crate::shell::Shell;
+    use crate::test_support;
 
-    if let Some(dir) = existing_dir {
-        dir.last_accessed_timestamp = now;
-        dir.times += 1;
-    } else {
-        existing_data.visited_dirs.push(VisitedDir {
-            dir: new_full_path,
-            last_accessed_timestamp: now,
-            times: 1,
-        });
-    }
-
-    let max_dirs = 100;
-    if existing_data.visited_dirs.len() > max_dirs {
-        existing_data
-            .visited_dirs
-            .sort_by(|a, b| b.times.partial_cmp(&a.times).unwrap());
-        existing_data.visited_dirs.pop();
-    }
-
-    // overwrite the file with the new data
-    file.seek(std::io::SeekFrom::Start(0))?;
-    serde_json::to_writer(&file, &existing_data)?;
-    let end = file.seek(std::io::SeekFrom::End(0))?;
-    file.set_len(end)?;
-    return Ok(());
-}
+    fn median(mut timings: Vec<Duration>) -> Duration {
+        timings.sort();
+        return timings[timings.len() / 2];
+    }
 
-#[derive(Serialize, Deserialize)]
-struct DirectoryHistory {
-    visited_dirs: Vec<VisitedDir>,
-}
+    fn score(dir: &str, keywords: &[&str]) -> Option<i64> {
+        return match_segments(dir, keywords, &SkimMatcherV2::default().smart_case());
+    }
+
+    #[test]
+    fn keywords_match_segments_in_order() {
+        assert!(score(r"C:\repo1\src\frontend", &["repo1", "src", "frontend"]).is_some());
+        assert!(score(r"C:\repo1\src\frontend", &["repo1", "front"]).is_some());
+        assert!(score(r"C:\repo1\src\frontend", &["src", "repo1", "frontend"]).is_none());
+        // Each keyword takes a segment of its own
+        assert!(score(r"C:\repo1\frontend", &["front", "frontend"]).is_none());
+    }
+
+    #[test]
+    fn last_keyword_matches_the_basename() {
+        assert!(score(r"C:\repo1\src\frontend", &["frontend"]).is_some());
+        assert!(score(r"C:\repo1\src\frontend", &["repo1"]).is_none());
+        assert!(score(r"C:\repo1\src\frontend", &["frontend", "src"]).is_none());
+    }
+
+    #[test]
+    fn basename_weighs_more_than_the_rest_of_the_path() {
+        let matcher = SkimMatcherV2::default().smart_case();
+        let basename = matcher.fuzzy_match("frontend", "front").unwrap();
+        let parent = matcher.fuzzy_match("repo1", "repo").unwrap();
+        assert_eq!(score(r"C:\repo1\frontend", &["repo", "front"]), Some(2 * basename + parent));
+    }
+
+    #[test]
+    fn uppercase_keywords_match_case_sensitively() {
+        assert!(score(r"C:\Work\Frontend", &["work", "front"]).is_some());
+        assert!(score(r"C:\work\frontend", &["Front"]).is_none());
+        assert!(score(r"C:\work\Frontend", &["Front"]).is_some());
+    }
+
+    /// The prompt hook runs on every directory change, the size of the history must not slow it down.
+    #[test]
+    #[ignore = "measures wall-clock time, run with `cargo test --release -- --ignored`"]
+    fn prompt_hook_is_fast_with_a_large_history() {
+        let (_guard, data_folder) = test_support::empty_data_folder();
+        let previous_dir = data_folder.join("work").join("api").join("src").join("handlers");
+        let new_dir = data_folder.join("work").join("web").join("src").join("components");
+        std::fs::create_dir_all(&previous_dir).unwrap();
+        std::fs::create_dir_all(&new_dir).unwrap();
+        let previous_dir = previous_dir.to_str().unwrap();
+        let new_dir = new_dir.to_str().unwrap();
+
+        history::modify(|history| {
+            for i in 0..5_000 {
+                history.add_visit(format!(r"C:\work\project-{i}\src"), i);
+            }
+        }).unwrap();
+
+        let mut timings = vec![];
+        for _ in 0..50 {
+            let started = Instant::now();
+            on_changed_directory(new_dir, Some("timing-test")).unwrap();
+            hooks::on_changed_directory(Some(previous_dir), new_dir, Shell::Powershell).unwrap();
+            timings.push(started.elapsed());
+        }
 
-#[derive(Serialize, Deserialize)]
-struct VisitedDir {
-    dir: String,
-    last_accessed_timestamp: u64,
-    times: u32,
+        // A few ms in release builds, debug builds and slow disks get some slack
+        let median = median(timings);
+        assert!(median < Duration::from_millis(10), "the prompt hook took {median:?}");
+    }
+
+    /// Once in a while the hook folds the log into the history, that has to stay bearable too.
+    #[test]
+    #[ignore = "measures wall-clock time, run with `cargo test --release -- --ignored`"]
+    fn prompt_hook_compacts_a_full_log_quickly() {
+        let (_guard, data_folder) = test_support::empty_data_folder();
+        let dir = data_folder.join("work").join("api");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_str().unwrap();
+
+        history::modify(|history| {
+            for i in 0..5_000 {
+                history.add_visit(format!(r"C:\work\project-{i}\src"), i);
+            }
+        }).unwrap();
+
+        let log_path = data_folder.join("directory_visits.log");
+        let mut timings = vec![];
+        for _ in 0..5 {
+            while std::fs::metadata(&log_path).map_or(0, |m| m.len()) < 64 * 1024 {
+                visit_log::append(history::now_timestamp().unwrap(), dir).unwrap();
+            }
+
+            let started = Instant::now();
+            on_changed_directory(dir, None).unwrap();
+            timings.push(started.elapsed());
+            assert!(!log_path.exists(), "the full log was not compacted");
+        }
+
+        let median = median(timings);
+        assert!(median < Duration::from_millis(200), "compacting the log took {median:?}");
+    }
 }
@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::actions::ActionSettings;
+use crate::layout::Layout;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const APP_NAME: &str = "wterm-sessionizer";
+const FILE_NAME: &str = "config.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// The settings of every part of wts. Layered from the defaults, config.toml from the config folder,
+/// the file from WTS_CONFIG or --config, and the --set flags, each one overriding the ones before it.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Config {
+    /// Where the history, favorites and the rest of the data are kept, the data folder of the user by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_folder: Option<PathBuf>,
+    pub discovery: DiscoveryConfig,
+    pub history: HistoryConfig,
+    pub dialogue: DialogueConfig,
+    pub picker: PickerConfig,
+    pub launcher: LauncherConfig,
+    /// The editor and what Enter does in the picker, per language
+    pub actions: ActionSettings,
+    /// Layouts for projects that do not have a .wts.toml of their own
+    pub layouts: Vec<ProjectLayout>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProjectLayout {
+    pub path: String,
+    #[serde(flatten)]
+    pub layout: Layout,
+}
+
+/// How projects are searched for.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct DiscoveryConfig {
+    /// Folders with these names are never searched, e.g. dependencies
+    pub skip: Vec<String>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        return DiscoveryConfig { skip: vec!["node_modules".to_string()] };
+    }
+}
+
+/// The [history] section, e.g.
+/// ```toml
+/// [history]
+/// exclude = ["~", "C:/temp/**", "**/bin/Debug/**"]
+/// min_depth = 2
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct HistoryConfig {
+    /// Globs of directories that are never recorded, `~` and `$VARIABLES` are expanded.
+    /// Replaces the default list, which skips the home folder, temp, windows and node_modules.
+    pub exclude: Vec<String>,
+    /// Directories with fewer segments than this are not recorded, 1 skips the drive roots
+    pub min_depth: usize,
+    /// Once the visits of all directories add up to more than this, they are aged
+    pub max_visits: f64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        let exclude = ["~", "$TEMP", "$TEMP/**", "$SystemRoot", "$SystemRoot/**", "**/node_modules", "**/node_modules/**"];
+        return HistoryConfig {
+            exclude: exclude.iter().map(|e| e.to_string()).collect(),
+            min_depth: 1,
+            max_visits: 10_000.0,
+        };
+    }
+}
+
+/// The built-in picker.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct DialogueConfig {
+    /// How many matches are visible under the prompt at once, as many as fit the terminal when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct PickerConfig {
+    /// An external picker like fzf, used unless --picker or WTS_PICKER say otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+/// How projects are opened in Windows Terminal.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct LauncherConfig {
+    /// The wt command line that the new tab and split pane subcommands are added to
+    pub wt: String,
+}
+
+impl Default for LauncherConfig {
+    fn default() -> Self {
+        return LauncherConfig { wt: "wt -w 0".to_string() };
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    return dirs::config_dir().unwrap().join(APP_NAME).join(FILE_NAME);
+}
+
+/// Loads the config once before the command runs, so that a broken config is reported right away.
+pub fn init(config_file: Option<String>, overrides: &[String]) -> Result<()> {
+    let config = load(config_file, overrides)?;
+    return CONFIG.set(config).map_err(|_| "The config was already loaded or used before it was loaded".into());
+}
+
+/// The effective config, the defaults when `init` was not called.
+pub fn get() -> &'static Config {
+    // Tests never read the config or the data of the user
+    #[cfg(test)]
+    return CONFIG.get_or_init(crate::test_support::config);
+
+    #[cfg(not(test))]
+    return CONFIG.get_or_init(Config::default);
+}
+
+/// Prints the effective config, with every default filled in.
+pub fn show() -> Result<()> {
+    print!("{}", toml::to_string_pretty(get())?);
+    return Ok(());
+}
+
+fn load(config_file: Option<String>, overrides: &[String]) -> Result<Config> {
+    let mut merged = toml::Table::new();
+
+    let mut files = vec![config_path()];
+    files.extend(config_file.filter(|f| !f.is_empty()).map(PathBuf::from));
+    for (idx, path) in files.iter().enumerate() {
+        // The default file is optional, an explicitly given one is not
+        if idx == 0 && !path.exists() {
+            continue;
+        }
+
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config {}: {e}", path.display()))?;
+        merge(&mut merged, parse_layer(&content).map_err(|e| format!("Invalid config {}: {e}", path.display()))?);
+    }
+
+    for set in overrides {
+        merge(&mut merged, parse_override(set).map_err(|e| format!("Invalid --set '{set}': {e}"))?);
+    }
+
+    let config = merged.try_into::<Config>()
+        .map_err(|e| format!("Invalid config: {e}"))?;
+    config.validate()
+        .map_err(|e| format!("Invalid config: {e}"))?;
+    return Ok(config);
+}
+
+/// Each layer is checked on its own, so that the error points at the file that has it.
+fn parse_layer(content: &str) -> Result<toml::Table> {
+    toml::from_str::<Config>(content)?;
+    return Ok(toml::from_str::<toml::Table>(content)?);
+}
+
+/// `section.key=value`, where the value is toml, or a plain string when it does not parse as toml.
+fn parse_override(set: &str) -> Result<toml::Table> {
+    let Some((key, value)) = set.split_once('=') else {
+        return Err("expected key=value".into());
+    };
+    let (key, value) = (key.trim(), value.trim());
+
+    let table = toml::from_str::<toml::Table>(&format!("{key} = {value}"))
+        .or_else(|_| toml::from_str::<toml::Table>(&format!("{key} = {}", toml::Value::String(value.to_string()))))?;
+    return parse_layer(&table.to_string());
+}
+
+/// Tables are merged key by key, anything else from the layer replaces what was there.
+fn merge(base: &mut toml::Table, layer: toml::Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(layer_table)) => merge(base_table, layer_table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+impl Config {
+    fn validate(&self) -> Result<()> {
+        if self.data_folder.as_ref().is_some_and(|f| !f.is_absolute()) {
+            return Err("data_folder must be an absolute path".into());
+        }
+
+        if self.dialogue.height == Some(0) {
+            return Err("dialogue.height must be at least 1".into());
+        }
+
+        if self.history.max_visits.is_nan() || self.history.max_visits <= 0.0 {
+            return Err("history.max_visits must be more than 0".into());
+        }
+
+        if let Some(pattern) = self.history.exclude.iter().find(|e| glob::Pattern::new(e).is_err()) {
+            return Err(format!("history.exclude has an invalid glob '{pattern}'").into());
+        }
+
+        if self.launcher.wt.trim().is_empty() {
+            return Err("launcher.wt can not be empty".into());
+        }
+
+        if let Some(layout) = self.layouts.iter().find(|l| l.path.trim().is_empty()) {
+            return Err(format!("a layout with {} tabs has no path", layout.layout.tabs.len()).into());
+        }
+
+        return Ok(());
+    }
+}
@@ -1,6 +1,4 @@
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-use std::fmt::{Display, Formatter};
+use std::fmt::Display;
 use std::io;
 use std::io::Write;
 use std::ops::Deref;
@@ -10,10 +8,13 @@ use std::time::Duration;
 
 use dialoguer::console::{Key, style, StyledObject};
 use dialoguer::console::Term;
-use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use indicatif::TermLike;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+use super::input_buffer::InputBuffer;
+use super::ranking;
+use super::ranking::{Prediction, Query};
 use super::windows_input;
 
 pub enum DialogueMessage<T> {
@@ -23,17 +24,68 @@ pub enum DialogueMessage<T> {
     ForceShutdown,
 }
 
+/// Items shown in the dialogue. The default implementations describe a plain item.
+pub trait DialogueItem: Display + Eq + Clone {
+    /// Pinned items are always listed before the rest of the matches.
+    fn is_pinned(&self) -> bool {
+        false
+    }
+
+    /// An alias typed in full wins over any fuzzy match.
+    fn alias(&self) -> Option<&str> {
+        None
+    }
+
+    /// Tags that can be filtered on with `#tag` in the query.
+    fn tags(&self) -> Vec<&str> {
+        vec![]
+    }
+
+    /// The part of the shown text that the query is matched against, without decorations like the pin,
+    /// so that `^` and `$` anchor at the ends of e.g. the path.
+    fn search_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl DialogueItem for String {}
+
+/// The chosen item, together with the key that confirmed the choice.
+pub struct Selection<T> {
+    pub item: T,
+    pub key: Key,
+    /// The items marked in the multi-select mode, in the order they were marked
+    pub marked: Vec<T>,
+}
+
+impl<T> Selection<T> {
+    /// The marked items, or the chosen one when nothing was marked.
+    pub fn items(self) -> Vec<T> {
+        if self.marked.is_empty() {
+            return vec![self.item];
+        }
+        return self.marked;
+    }
+}
+
+const CTRL_A: Key = Key::Char('\u{1}');
+const CTRL_U: Key = Key::Char('\u{15}');
+const CTRL_W: Key = Key::Char('\u{17}');
+
 pub struct Dialogue<T> {
     items: Vec<T>,
     additional_items_receiver: Receiver<DialogueMessage<T>>,
     current_progress: Option<String>,
     prompt: String,
     force_shutdown: bool,
+    confirm_keys: Vec<Key>,
+    height: Option<usize>,
+    multi_select: bool,
 }
 
-impl<T> Dialogue<T> where T: Display, T: Eq, T: Clone {
+impl<T> Dialogue<T> where T: DialogueItem {
     pub fn new(receiver: Receiver<DialogueMessage<T>>) -> Dialogue<T> {
-        Dialogue { items: vec![], additional_items_receiver: receiver, current_progress: None, prompt: "".to_string(), force_shutdown: false }
+        Dialogue { items: vec![], additional_items_receiver: receiver, current_progress: None, prompt: "".to_string(), force_shutdown: false, confirm_keys: vec![], height: None, multi_select: false }
     }
 
     pub fn prompt(&mut self, str: &str) -> &mut Dialogue<T> {
@@ -41,15 +93,39 @@ impl<T> Dialogue<T> where T: Display, T: Eq, T: Clone {
         return self;
     }
 
-    pub fn interact(&mut self) -> io::Result<Option<T>> {
+    /// Keys that choose the selected item in addition to Enter, e.g. Ctrl+T
+    pub fn confirm_keys(&mut self, keys: Vec<Key>) -> &mut Dialogue<T> {
+        self.confirm_keys = keys;
+        return self;
+    }
+
+    /// How many matches are visible under the prompt at once, as many as fit the terminal by default
+    pub fn height(&mut self, height: usize) -> &mut Dialogue<T> {
+        self.height = Some(height);
+        return self;
+    }
+
+    /// Tab marks the highlighted item and Ctrl+A all the matching ones, Enter then chooses every marked item
+    pub fn multi_select(&mut self) -> &mut Dialogue<T> {
+        self.multi_select = true;
+        return self;
+    }
+
+    /// The chosen items, more than one only in the multi-select mode. Empty when nothing was chosen.
+    pub fn interact(&mut self) -> io::Result<Vec<T>> {
+        return Ok(self.interact_selection()?.map_or(vec![], |s| s.items()));
+    }
+
+    pub fn interact_selection(&mut self) -> io::Result<Option<Selection<T>>> {
         let mut renderer = Renderer::new();
         let mut full_input = CurrentInput {
-            cursor: 0,
-            input: "".to_string(),
+            input: InputBuffer::default(),
+            query: Query::parse(""),
             predictions: vec![],
-            max_predictions: 10,
+            scroll: 0,
             matcher: SkimMatcherV2::default().ignore_case(),
             selected: None,
+            marked: vec![],
         };
 
         'outer: loop {
@@ -68,23 +144,35 @@ impl<T> Dialogue<T> where T: Display, T: Eq, T: Clone {
                 None => {}
             }
 
+            let height = self.visible_height(&renderer);
+            full_input.scroll_to_selected(height);
+
             let prompt = &self.prompt.clone();
-            let choose_prompt = format!("{prompt}: ");
+            let counter = if full_input.marked.is_empty() {
+                format!("{}/{}", full_input.predictions.len(), self.items.len())
+            } else {
+                format!("{}/{}, {} marked", full_input.predictions.len(), self.items.len(), full_input.marked.len())
+            };
+            let choose_prompt = format!("{prompt} ({counter}): ");
             renderer.write_prompt(&choose_prompt)?;
             let position = renderer.get_position();
 
-            renderer.write_line(&full_input.input)?;
+            renderer.write_line(full_input.input.as_str())?;
 
-            for (idx, item) in full_input.predictions.iter().enumerate() {
+            let visible = full_input.predictions.iter().enumerate().skip(full_input.scroll).take(height);
+            for (idx, item) in visible {
                 let is_selected = match &full_input.selected {
                     Some(s) => s.idx == idx,
                     None => false
                 };
-                renderer.write_selection_item(&item.to_string(), is_selected)?;
+                let is_marked = full_input.marked.contains(&item.item);
+                let text = item.to_string();
+                let matched = Self::matched_indices(&text, &item.item, &full_input);
+                renderer.write_selection_item(&text, &matched, is_selected, is_marked)?;
             }
 
             let end_position = renderer.get_position();
-            renderer.move_cursor_to(&position.with_x(position.x + full_input.input.len()))?;
+            renderer.move_cursor_to(&position.with_x(position.x + full_input.input.cursor_width()))?;
 
             renderer.term.show_cursor()?;
             let key = loop {
@@ -100,77 +188,86 @@ impl<T> Dialogue<T> where T: Display, T: Eq, T: Clone {
 
                 sleep(Duration::from_millis(10));
             };
+            if key == Key::Enter || self.confirm_keys.contains(&key) {
+                // Marked items can be chosen even when the query does not match anything anymore
+                let item = full_input.selected.as_ref().map(|s| s.item.clone()).or_else(|| full_input.marked.first().cloned());
+                match item {
+                    Some(item) => {
+                        renderer.move_cursor_to(&end_position)?;
+                        renderer.clear()?;
+                        match full_input.marked.len() {
+                            0 | 1 => renderer.write_successful("Choose: ", full_input.marked.first().unwrap_or(&item))?,
+                            count => renderer.write_successful("Choose: ", &format!("{count} items"))?,
+                        }
+                        return Ok(Some(Selection { item, key, marked: full_input.marked }));
+                    }
+                    None => {}
+                }
+            }
+
             match key
             {
-                Key::Char(char) => {
-                    if full_input.input.len() + prompt.len() < renderer.get_max_input_size() {
-                        full_input.input.insert(full_input.cursor, char);
-                        full_input.cursor += 1;
+                Key::Tab if self.multi_select => {
+                    if let Some(selected) = &full_input.selected {
+                        let idx = selected.idx;
+                        full_input.toggle_mark(idx);
+                        // Moving on, so that a run of items can be marked by pressing Tab repeatedly
+                        full_input.select((idx + 1).min(full_input.predictions.len() - 1));
                     }
                 }
-                Key::Backspace => {
-                    if full_input.input.len() > 0 {
-                        full_input.input.remove(full_input.cursor - 1);
-                        full_input.cursor -= 1;
-                    }
+                CTRL_A if self.multi_select => full_input.mark_all(),
+                CTRL_U => full_input.input.delete_to_start(),
+                CTRL_W => full_input.input.delete_big_word(),
+                // Control characters come from the shortcuts that are not handled
+                Key::Char(char) if char.is_control() => {}
+                Key::Char(char) => {
+                    // A paste arrives as a burst of keys, it is inserted at once instead of redrawing after every key
+                    let mut text = char.to_string();
+                    text.push_str(&windows_input::read_pending_text()?);
+                    let max_width = renderer.get_max_input_size().saturating_sub(choose_prompt.width() + 1);
+                    full_input.input.insert(&text, max_width);
                 }
+                Key::Backspace => full_input.input.backspace(),
+                Key::Del => full_input.input.delete(),
+                key if key == windows_input::alt_backspace() => full_input.input.delete_word(),
                 Key::Escape => {
                     renderer.move_cursor_to(&end_position)?;
                     return Ok(None);
                 }
-                Key::ArrowLeft => {
-                    if full_input.cursor > 0 {
-                        full_input.cursor -= 1;
-                    }
-                }
-                Key::ArrowRight => {
-                    if full_input.cursor < full_input.input.len() {
-                        full_input.cursor += 1;
-                    }
-                }
+                Key::ArrowLeft => full_input.input.move_left(),
+                Key::ArrowRight => full_input.input.move_right(),
+                Key::Home => full_input.input.move_home(),
+                Key::End => full_input.input.move_end(),
+                key if key == windows_input::with_ctrl(Key::ArrowLeft) => full_input.input.move_word_left(),
+                key if key == windows_input::with_ctrl(Key::ArrowRight) => full_input.input.move_word_right(),
                 Key::ArrowUp => {
-                    if full_input.predictions.len() == 0 {
-                        full_input.selected = None;
-                    } else {
-                        let last_idx = full_input.predictions.len() - 1;
-                        let next_idx = match &full_input.selected {
-                            Some(s) => if s.idx == 0 { last_idx } else { s.idx - 1 },
-                            None => last_idx
-                        };
-
-                        full_input.selected = Some(Selected {
-                            idx: next_idx,
-                            item: full_input.predictions.get(next_idx).unwrap().item.clone(),
-                        })
-                    }
+                    let last_idx = full_input.predictions.len().saturating_sub(1);
+                    let next_idx = match &full_input.selected {
+                        Some(s) => if s.idx == 0 { last_idx } else { s.idx - 1 },
+                        None => last_idx
+                    };
+                    full_input.select(next_idx);
                 }
                 Key::ArrowDown => {
-                    if full_input.predictions.len() == 0 {
-                        full_input.selected = None;
-                    } else {
-                        let last_idx = full_input.predictions.len() - 1;
-                        let next_idx = match &full_input.selected {
-                            Some(s) => if s.idx == last_idx { 0 } else { s.idx + 1 },
-                            None => 0
-                        };
-
-                        full_input.selected = Some(Selected {
-                            idx: next_idx,
-                            item: full_input.predictions.get(next_idx).unwrap().item.clone(),
-                        })
-                    }
+                    let last_idx = full_input.predictions.len().saturating_sub(1);
+                    let next_idx = match &full_input.selected {
+                        Some(s) => if s.idx == last_idx { 0 } else { s.idx + 1 },
+                        None => 0
+                    };
+                    full_input.select(next_idx);
                 }
-                Key::Enter => {
-                    match full_input.selected {
-                        Some(selection) => {
-                            renderer.move_cursor_to(&end_position)?;
-                            renderer.clear()?;
-                            renderer.write_successful("Choose: ", &selection.item)?;
-                            return Ok(Some(selection.item.clone()));
-                        }
-                        None => {}
-                    }
+                // Paging stops at the ends of the list instead of wrapping around
+                Key::PageUp => {
+                    let selected_idx = full_input.selected.as_ref().map_or(0, |s| s.idx);
+                    full_input.select(selected_idx.saturating_sub(height));
                 }
+                Key::PageDown => {
+                    let selected_idx = full_input.selected.as_ref().map_or(0, |s| s.idx);
+                    let last_idx = full_input.predictions.len().saturating_sub(1);
+                    full_input.select((selected_idx + height).min(last_idx));
+                }
+                key if key == windows_input::with_ctrl(Key::Home) => full_input.select(0),
+                key if key == windows_input::with_ctrl(Key::End) => full_input.select(full_input.predictions.len().saturating_sub(1)),
                 _ => {}
             }
 
@@ -186,34 +283,49 @@ impl<T> Dialogue<T> where T: Display, T: Eq, T: Clone {
     }
 
     fn fill_predictions(&self, input: &mut CurrentInput<T>) {
-        // TODO: do we *really* need to allocate a heap here?
-        // This should be a min-heap cause we want the top scores here
-        let mut binary_heap = BinaryHeap::<Prediction<T>>::with_capacity(input.max_predictions);
-
-        let items = self.items.iter().map(|i| (i, input.matcher.fuzzy_match(&format!("{}", i), &input.input)));
-        for (item, score) in items {
-            match score {
-                Some(score) =>
-                    if binary_heap.len() < input.max_predictions {
-                        binary_heap.push(Prediction { score, item: item.clone() });
-                    } else {
-                        if let Some(min_element) = binary_heap.peek() {
-                            if score > min_element.score {
-                                binary_heap.pop();
-                                binary_heap.push(Prediction { score, item: item.clone() });
-                            }
-                        }
-                    },
-                _ => {}
-            }
-        }
-
-        input.predictions = binary_heap.into_sorted_vec().iter().rev()
-            .map(|x| x.clone()).collect();
+        // All the matches are kept, so that the list can be scrolled through
+        input.query = Query::parse(input.input.as_str());
+        input.predictions = ranking::rank(&self.items, &input.query, &input.matcher, usize::MAX);
         input.selected = self.get_new_selected(input);
     }
 
+    /// Char indices in the shown text of the characters that the query matched in the search text.
+    fn matched_indices(text: &str, item: &T, input: &CurrentInput<T>) -> Vec<usize> {
+        let search_text = item.search_text();
+        let Some(byte_offset) = text.find(&search_text) else {
+            return vec![];
+        };
+
+        let offset = text[..byte_offset].chars().count();
+        return input.query.matched_indices(&search_text, &input.matcher).into_iter()
+            .map(|idx| idx + offset)
+            .collect();
+    }
+
+    /// The configured height, but never more rows than the terminal has left under the prompt.
+    fn visible_height(&self, renderer: &Renderer) -> usize {
+        let (terminal_rows, _) = renderer.term.size();
+        // The prompt, the progress and an empty line, so that writing the last item does not scroll the terminal
+        let reserved_rows = if self.current_progress.is_some() { 3 } else { 2 };
+        let available_rows = (terminal_rows as usize).saturating_sub(reserved_rows).max(1);
+
+        return match self.height {
+            Some(height) => height.min(available_rows),
+            None => available_rows,
+        };
+    }
+
     fn get_new_selected(&self, input: &CurrentInput<T>) -> Option<Selected<T>> {
+        if let Some(first) = input.predictions.first() {
+            if first.is_alias_match() {
+                // An exact alias match should be picked by Enter right away
+                return Some(Selected {
+                    idx: 0,
+                    item: first.item.clone(),
+                });
+            }
+        }
+
         match &input.selected {
             Some(selected) => {
                 if let Some(position) = input.predictions.iter().position(|x| x.item == selected.item) {
@@ -266,46 +378,71 @@ impl<T> Dialogue<T> where T: Display, T: Eq, T: Clone {
     }
 }
 
-#[derive(Clone)]
-struct Prediction<T> {
-    item: T,
-    score: i64,
+struct CurrentInput<T> {
+    input: InputBuffer,
+    /// The input parsed for the matching
+    query: Query,
+    /// All the matches, best first
+    predictions: Vec<Prediction<T>>,
+    /// Index of the first visible prediction
+    scroll: usize,
+    matcher: SkimMatcherV2,
+    selected: Option<Selected<T>>,
+    /// Kept while the query changes, so that items from different searches can be chosen together
+    marked: Vec<T>,
 }
 
-impl<T> Display for Prediction<T> where T: Display {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{}", self.item))
+impl<T> CurrentInput<T> where T: Clone + Eq {
+    fn toggle_mark(&mut self, idx: usize) {
+        let Some(prediction) = self.predictions.get(idx) else {
+            return;
+        };
+
+        match self.marked.iter().position(|m| *m == prediction.item) {
+            Some(position) => {
+                self.marked.remove(position);
+            }
+            None => self.marked.push(prediction.item.clone()),
+        }
     }
-}
 
-impl<T> Eq for Prediction<T> {}
+    /// Marks every matching item, or unmarks them when all of them are marked already.
+    fn mark_all(&mut self) {
+        let all_marked = self.predictions.iter().all(|p| self.marked.contains(&p.item));
+        if all_marked {
+            self.marked.retain(|m| !self.predictions.iter().any(|p| p.item == *m));
+            return;
+        }
 
-impl<T> PartialEq<Self> for Prediction<T> {
-    fn eq(&self, other: &Self) -> bool {
-        return self.score.eq(&other.score);
+        for prediction in &self.predictions {
+            if !self.marked.contains(&prediction.item) {
+                self.marked.push(prediction.item.clone());
+            }
+        }
     }
-}
 
-impl<T> PartialOrd<Self> for Prediction<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        return self.score.partial_cmp(&other.score);
+    fn select(&mut self, idx: usize) {
+        self.selected = self.predictions.get(idx).map(|p| Selected {
+            idx,
+            item: p.item.clone(),
+        });
     }
-}
 
-impl<T> Ord for Prediction<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        return self.score.cmp(&other.score);
+    /// Moves the visible part of the list, so that the selected item is in it.
+    fn scroll_to_selected(&mut self, height: usize) {
+        let max_scroll = self.predictions.len().saturating_sub(height);
+        if let Some(selected) = &self.selected {
+            if selected.idx < self.scroll {
+                self.scroll = selected.idx;
+            } else if selected.idx >= self.scroll + height {
+                self.scroll = selected.idx + 1 - height;
+            }
+        }
+        self.scroll = self.scroll.min(max_scroll);
     }
 }
 
-struct CurrentInput<T> {
-    input: String,
-    cursor: usize,
-    predictions: Vec<Prediction<T>>,
-    max_predictions: usize,
-    matcher: SkimMatcherV2,
-    selected: Option<Selected<T>>,
-}
+const TRIM_PADDING: &str = "...";
 
 struct Renderer {
     lines_number: usize,
@@ -351,22 +488,30 @@ impl Renderer {
     }
 
     fn trimmed_max_size(&self, input: &str, max_width: usize) -> String {
-        // We need to split on character boundaries, so we deal in characters here.
-        let padding_str = "...";
-        let padding_char_length = padding_str.len();
-        let length = input.char_indices().count();
-
-        if length < max_width {
+        let kept_chars = self.kept_chars(input, max_width);
+        if kept_chars == input.chars().count() {
             return input.to_string();
         }
 
-        if length <= padding_char_length {
-            return input.to_string();
+        return input.chars().take(kept_chars).collect::<String>() + TRIM_PADDING;
+    }
+
+    /// How many characters of the input are left after trimming it to `max_width` columns, without the padding.
+    fn kept_chars(&self, input: &str, max_width: usize) -> usize {
+        // We need to split on character boundaries, so we deal in characters here,
+        // but measure them in columns, as CJK and emoji like the pin take two of them.
+        if input.width() < max_width {
+            return input.chars().count();
         }
 
-        let last_writable_index = input.char_indices().nth(max_width - 3).unwrap().0;
-        let (left, _) = input.split_at(last_writable_index);
-        return left.to_string() + padding_str;
+        let available_width = max_width.saturating_sub(TRIM_PADDING.len());
+        let mut width = 0;
+        return input.chars()
+            .take_while(|c| {
+                width += c.width().unwrap_or(0);
+                width <= available_width
+            })
+            .count();
     }
 
     fn write_line(&mut self, message: &str) -> io::Result<()> {
@@ -397,35 +542,68 @@ impl Renderer {
         self.write(styled_object.to_string().as_str())
     }
 
-    fn write_selection_item(&mut self, item: &str, selected: bool) -> io::Result<()> {
+    /// Writes a result row, with the characters at the `matched` char indices highlighted.
+    fn write_selection_item(&mut self, item: &str, matched: &[usize], selected: bool, marked: bool) -> io::Result<()> {
         let padding_left = 3;
 
-        let item = self.trimmed_max_size(item, (self.term.width() - padding_left) as usize);
-        let styled_message = if selected {
-            let prefix = style("❯").for_stderr().green().to_string() + (0..padding_left - 1).map(|_| " ").collect::<String>().as_str();
-            let item = prefix + style(&item).for_stderr().cyan().to_string().as_str();
-            style(item).bold()
+        let kept_chars = self.kept_chars(item, (self.term.width() - padding_left) as usize);
+        // The gutter has the cursor in the first column and the mark in the second one
+        let mut message = if selected {
+            style("❯").for_stderr().green().bold().to_string()
+        } else {
+            " ".to_string()
+        };
+        message += &if marked {
+            style("●").for_stderr().magenta().bold().to_string()
         } else {
-            let prefix = (0..padding_left).map(|_| " ").collect::<String>();
-            let item = prefix + &item;
-            style(item)
+            " ".to_string()
         };
+        message += " ";
+
+        // Consecutive characters with the same highlighting are styled together
+        let mut segment = String::new();
+        let mut segment_matched = false;
+        for (idx, char) in item.chars().take(kept_chars).enumerate() {
+            let is_matched = matched.contains(&idx);
+            if is_matched != segment_matched && !segment.is_empty() {
+                message += &Self::style_segment(&segment, segment_matched, selected);
+                segment.clear();
+            }
+            segment_matched = is_matched;
+            segment.push(char);
+        }
+        message += &Self::style_segment(&segment, segment_matched, selected);
+        if kept_chars < item.chars().count() {
+            message += &Self::style_segment(TRIM_PADDING, false, selected);
+        }
 
-        self.cursor_position.x = self.cursor_position.x + padding_left as usize + item.len();
-        self.write_line_formatted(styled_message)
+        let kept_width = item.chars().take(kept_chars).map(|c| c.width().unwrap_or(0)).sum::<usize>();
+        let padding_width = if kept_chars < item.chars().count() { TRIM_PADDING.len() } else { 0 };
+        self.cursor_position.x = self.cursor_position.x + padding_left as usize + kept_width + padding_width;
+        self.write_line_formatted(style(message))
+    }
+
+    fn style_segment(segment: &str, matched: bool, selected: bool) -> String {
+        let styled = style(segment).for_stderr();
+        let styled = match (matched, selected) {
+            (true, _) => styled.yellow().bold(),
+            (false, true) => styled.cyan().bold(),
+            (false, false) => styled,
+        };
+        return styled.to_string();
     }
 
     fn write_prompt(&mut self, prompt: &str) -> io::Result<()> {
         let padding_left = 3;
         let prefix = style("?").for_stderr().yellow().to_string() + (0..padding_left - 1).map(|_| " ").collect::<String>().as_str();
-        self.cursor_position.x = self.cursor_position.x + padding_left + prompt.len();
+        self.cursor_position.x = self.cursor_position.x + padding_left + prompt.width();
         self.write_formatted(style(prefix + prompt).bold())
     }
 
     pub fn write_successful<T>(&mut self, successful_message: &str, item: &T) -> io::Result<()> where T: Display {
         let padding_left = 3;
         let prefix = style("✔").for_stderr().yellow().to_string() + (0..padding_left - 1).map(|_| " ").collect::<String>().as_str();
-        self.cursor_position.x = self.cursor_position.x + padding_left + successful_message.len() + item.to_string().len();
+        self.cursor_position.x = self.cursor_position.x + padding_left + successful_message.width() + item.to_string().width();
         self.write_line_formatted(style(prefix + successful_message + item.to_string().as_str()).for_stderr().bold())
     }
 
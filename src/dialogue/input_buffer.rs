@@ -0,0 +1,300 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// The text typed into the dialogue. The cursor is a byte offset that always sits between two graphemes,
+/// so that an accented letter or an emoji is moved over and deleted as one character.
+#[derive(Default)]
+pub struct InputBuffer {
+    text: String,
+    cursor: usize,
+}
+
+impl InputBuffer {
+    pub fn as_str(&self) -> &str {
+        return &self.text;
+    }
+
+    /// Columns the text takes in the terminal, wide characters like CJK take two.
+    pub fn width(&self) -> usize {
+        return self.text.width();
+    }
+
+    /// Columns between the start of the input and the cursor.
+    pub fn cursor_width(&self) -> usize {
+        return self.text[..self.cursor].width();
+    }
+
+    /// Inserts the text at the cursor, as long as the input stays within `max_width` columns.
+    /// Line breaks and tabs, e.g. from a paste, become spaces and other control characters are dropped.
+    pub fn insert(&mut self, text: &str, max_width: usize) {
+        let text = text.chars()
+            .filter_map(|c| match c {
+                '\r' | '\n' | '\t' => Some(' '),
+                c if c.is_control() => None,
+                c => Some(c),
+            })
+            .collect::<String>();
+
+        let mut width = self.width();
+        for grapheme in text.graphemes(true) {
+            width += grapheme.width();
+            if width > max_width {
+                break;
+            }
+
+            self.text.insert_str(self.cursor, grapheme);
+            self.cursor += grapheme.len();
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        let start = self.previous_boundary();
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    pub fn delete(&mut self) {
+        let end = self.next_boundary();
+        self.text.replace_range(self.cursor..end, "");
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.previous_boundary();
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = self.next_boundary();
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Moves to the start of the word before the cursor, words are letters and digits.
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.word_start(is_word);
+    }
+
+    /// Moves to the end of the word after the cursor.
+    pub fn move_word_right(&mut self) {
+        self.cursor = self.word_end(is_word);
+    }
+
+    /// Deletes the word before the cursor, like Alt+Backspace in a shell.
+    pub fn delete_word(&mut self) {
+        let start = self.word_start(is_word);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes everything up to the whitespace before the cursor, like Ctrl+W in a shell.
+    pub fn delete_big_word(&mut self) {
+        let start = self.word_start(is_not_whitespace);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    /// Deletes everything before the cursor, like Ctrl+U in a shell.
+    pub fn delete_to_start(&mut self) {
+        self.text.replace_range(..self.cursor, "");
+        self.cursor = 0;
+    }
+
+    fn previous_boundary(&self) -> usize {
+        return self.text[..self.cursor].grapheme_indices(true).next_back().map_or(0, |(idx, _)| idx);
+    }
+
+    fn next_boundary(&self) -> usize {
+        return self.cursor + self.text[self.cursor..].graphemes(true).next().map_or(0, |g| g.len());
+    }
+
+    /// Skips the separators right before the cursor, and then the word before them.
+    fn word_start(&self, is_word: fn(&str) -> bool) -> usize {
+        let mut start = self.cursor;
+        let mut in_word = false;
+        for (idx, grapheme) in self.text[..self.cursor].grapheme_indices(true).rev() {
+            if is_word(grapheme) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            start = idx;
+        }
+        return start;
+    }
+
+    /// Skips the separators right after the cursor, and then the word after them.
+    fn word_end(&self, is_word: fn(&str) -> bool) -> usize {
+        let mut end = self.cursor;
+        let mut in_word = false;
+        for (idx, grapheme) in self.text[self.cursor..].grapheme_indices(true) {
+            if is_word(grapheme) {
+                in_word = true;
+            } else if in_word {
+                break;
+            }
+            end = self.cursor + idx + grapheme.len();
+        }
+        return end;
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    return grapheme.chars().any(char::is_alphanumeric);
+}
+
+fn is_not_whitespace(grapheme: &str) -> bool {
+    return !grapheme.chars().all(char::is_whitespace);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> InputBuffer {
+        let mut buffer = InputBuffer::default();
+        buffer.insert(text, usize::MAX);
+        buffer
+    }
+
+    #[test]
+    fn cyrillic_insert_backspace_and_delete() {
+        let mut input = buffer("проект");
+        assert_eq!(input.cursor_width(), 6);
+
+        input.backspace();
+        assert_eq!(input.as_str(), "проек");
+
+        input.move_home();
+        input.delete();
+        assert_eq!(input.as_str(), "роек");
+        assert_eq!(input.cursor_width(), 0);
+
+        input.move_right();
+        input.insert("ж", usize::MAX);
+        assert_eq!(input.as_str(), "ржоек");
+        assert_eq!(input.cursor_width(), 2);
+    }
+
+    #[test]
+    fn cjk_takes_two_columns() {
+        let mut input = buffer("日本語");
+        assert_eq!(input.width(), 6);
+        assert_eq!(input.cursor_width(), 6);
+
+        input.move_left();
+        assert_eq!(input.cursor_width(), 4);
+
+        input.backspace();
+        assert_eq!(input.as_str(), "日語");
+        assert_eq!(input.cursor_width(), 2);
+    }
+
+    #[test]
+    fn emoji_is_one_grapheme() {
+        let mut input = buffer("a👍🏽b");
+        input.move_left();
+        input.move_left();
+        assert_eq!(input.cursor_width(), 1);
+
+        input.delete();
+        assert_eq!(input.as_str(), "ab");
+    }
+
+    #[test]
+    fn combining_mark_is_deleted_with_its_letter() {
+        let mut input = buffer("cafe\u{301}");
+        assert_eq!(input.cursor_width(), 4);
+
+        input.backspace();
+        assert_eq!(input.as_str(), "caf");
+
+        input.insert("e", usize::MAX);
+        input.insert("\u{301}", usize::MAX);
+        input.move_left();
+        assert_eq!(input.cursor_width(), 3);
+    }
+
+    #[test]
+    fn insert_stops_at_max_width() {
+        let mut input = buffer("ab");
+        input.insert("日c", 4);
+        assert_eq!(input.as_str(), "ab日");
+
+        input.insert("d", 4);
+        assert_eq!(input.as_str(), "ab日");
+    }
+
+    #[test]
+    fn pasted_line_breaks_become_spaces() {
+        let input = buffer("api\r\ngateway\tx\u{7}");
+        assert_eq!(input.as_str(), "api  gateway x");
+    }
+
+    #[test]
+    fn ctrl_w_deletes_up_to_whitespace() {
+        let mut input = buffer("wcd c:\\work\\проект  ");
+        input.delete_big_word();
+        assert_eq!(input.as_str(), "wcd ");
+
+        input.delete_big_word();
+        assert_eq!(input.as_str(), "");
+    }
+
+    #[test]
+    fn alt_backspace_deletes_one_word() {
+        let mut input = buffer("c:\\work\\проект\\");
+        input.delete_word();
+        assert_eq!(input.as_str(), "c:\\work\\");
+
+        input.delete_word();
+        assert_eq!(input.as_str(), "c:\\");
+    }
+
+    #[test]
+    fn ctrl_u_deletes_before_the_cursor() {
+        let mut input = buffer("日本 api");
+        input.move_word_left();
+        input.delete_to_start();
+        assert_eq!(input.as_str(), "api");
+        assert_eq!(input.cursor_width(), 0);
+    }
+
+    #[test]
+    fn home_end_and_word_jumps() {
+        let mut input = buffer("日本 web-app");
+        input.move_home();
+        assert_eq!(input.cursor_width(), 0);
+
+        input.move_word_right();
+        assert_eq!(input.cursor_width(), 4);
+        input.move_word_right();
+        assert_eq!(input.cursor_width(), 8);
+
+        input.move_end();
+        assert_eq!(input.cursor_width(), 12);
+        input.move_word_left();
+        assert_eq!(input.cursor_width(), 9);
+    }
+
+    #[test]
+    fn editing_at_the_edges_does_nothing() {
+        let mut input = InputBuffer::default();
+        input.backspace();
+        input.delete();
+        input.move_left();
+        input.delete_word();
+        input.delete_to_start();
+        assert_eq!(input.as_str(), "");
+
+        let mut input = buffer("ё");
+        input.delete();
+        input.move_right();
+        assert_eq!(input.as_str(), "ё");
+        assert_eq!(input.cursor_width(), 1);
+    }
+}
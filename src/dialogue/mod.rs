@@ -1,2 +1,4 @@
 pub mod dialogue_ui;
+pub mod input_buffer;
+pub mod ranking;
 pub mod windows_input;
@@ -0,0 +1,427 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt::{Display, Formatter};
+
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use super::dialogue_ui::DialogueItem;
+
+/// Scores the items against the query and returns the best ones, best first.
+/// `usize::MAX` returns all the matching items.
+pub fn rank<T>(items: &[T], query: &Query, matcher: &SkimMatcherV2, max_predictions: usize) -> Vec<Prediction<T>> where T: DialogueItem {
+    // This is a min-heap cause we want the top scores here
+    let mut binary_heap = BinaryHeap::<Reverse<Prediction<T>>>::with_capacity(max_predictions.min(items.len()));
+
+    let items = items.iter()
+        .filter(|i| query.matches_tags(*i))
+        .map(|i| (i, query.score(&i.search_text(), matcher)));
+    for (item, score) in items {
+        let tier = if item.alias().is_some_and(|a| !query.raw.is_empty() && a.eq_ignore_ascii_case(&query.raw)) {
+            PredictionTier::Alias
+        } else if item.is_pinned() {
+            PredictionTier::Pinned
+        } else {
+            PredictionTier::Regular
+        };
+
+        let score = match (score, &tier) {
+            (Some(score), _) => score,
+            (None, PredictionTier::Alias) => 0,
+            (None, _) => continue,
+        };
+
+        let prediction = Prediction { score, tier, item: item.clone() };
+        if binary_heap.len() < max_predictions {
+            binary_heap.push(Reverse(prediction));
+        } else {
+            if let Some(Reverse(min_element)) = binary_heap.peek() {
+                if prediction.cmp(min_element) == Ordering::Greater {
+                    binary_heap.pop();
+                    binary_heap.push(Reverse(prediction));
+                }
+            }
+        }
+    }
+
+    return binary_heap.into_sorted_vec().into_iter()
+        .map(|Reverse(x)| x).collect();
+}
+
+/// The user input in the fzf syntax: space separated terms that all have to match, `a | b` when either one is enough.
+/// A term is fuzzy, or `'exact`, `^prefix`, `suffix$` and `!negated`. `#tag` terms filter on the tags.
+pub struct Query {
+    raw: String,
+    tags: Vec<String>,
+    /// Every group has to match, one of the terms in the group is enough
+    groups: Vec<Vec<Term>>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Query {
+        let mut tags = vec![];
+        let mut groups: Vec<Vec<Term>> = vec![];
+        let mut continues_group = false;
+        for token in input.split_whitespace() {
+            if token == "|" {
+                continues_group = !groups.is_empty();
+                continue;
+            }
+
+            if let Some(tag) = token.strip_prefix('#').filter(|t| !t.is_empty()) {
+                tags.push(tag.to_lowercase());
+                continue;
+            }
+
+            // Terms that are only modifiers, e.g. a `!` that is still being typed, match everything
+            let Some(term) = Term::parse(token) else {
+                continue;
+            };
+            match groups.last_mut() {
+                Some(group) if continues_group => group.push(term),
+                _ => groups.push(vec![term]),
+            }
+            continues_group = false;
+        }
+
+        return Query {
+            raw: input.trim().to_string(),
+            tags,
+            groups,
+        };
+    }
+
+    /// The sum of the best score in every group, None when any of the groups does not match.
+    fn score(&self, text: &str, matcher: &SkimMatcherV2) -> Option<i64> {
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut score = 0;
+        for group in &self.groups {
+            score += group.iter().filter_map(|t| t.score(text, &chars, matcher)).max()?;
+        }
+        return Some(score);
+    }
+
+    /// Char indices of the characters in `text` that the terms matched, for highlighting.
+    pub fn matched_indices(&self, text: &str, matcher: &SkimMatcherV2) -> Vec<usize> {
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut indices = self.groups.iter()
+            .flatten()
+            .flat_map(|t| t.indices(text, &chars, matcher))
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        return indices;
+    }
+
+    fn matches_tags<T>(&self, item: &T) -> bool where T: DialogueItem {
+        if self.tags.is_empty() {
+            return true;
+        }
+
+        // Prefix matching, so that the list narrows down while the tag is being typed
+        let item_tags = item.tags();
+        return self.tags.iter()
+            .all(|tag| item_tags.iter().any(|t| t.to_lowercase().starts_with(tag.as_str())));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TermKind {
+    Fuzzy,
+    /// Anywhere in the text, but not scattered
+    Exact,
+    Prefix,
+    Suffix,
+    /// `^text$`, the whole text
+    Equal,
+}
+
+struct Term {
+    kind: TermKind,
+    text: Vec<char>,
+    negated: bool,
+}
+
+impl Term {
+    fn parse(token: &str) -> Option<Term> {
+        let (negated, token) = match token.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, token),
+        };
+
+        // Like in fzf, `'` turns a negated term back into a fuzzy one
+        let (kind, text) = if let Some(rest) = token.strip_prefix('\'') {
+            (if negated { TermKind::Fuzzy } else { TermKind::Exact }, rest)
+        } else if let Some(rest) = token.strip_prefix('^') {
+            match rest.strip_suffix('$') {
+                Some(rest) => (TermKind::Equal, rest),
+                None => (TermKind::Prefix, rest),
+            }
+        } else if let Some(rest) = token.strip_suffix('$') {
+            (TermKind::Suffix, rest)
+        } else {
+            (if negated { TermKind::Exact } else { TermKind::Fuzzy }, token)
+        };
+
+        if text.is_empty() {
+            return None;
+        }
+
+        return Some(Term { kind, text: text.chars().collect(), negated });
+    }
+
+    /// Negated terms add nothing to the score of the items that they let through.
+    fn score(&self, text: &str, chars: &[char], matcher: &SkimMatcherV2) -> Option<i64> {
+        let score = match self.kind {
+            TermKind::Fuzzy => matcher.fuzzy_match(text, &self.pattern()),
+            // Exact matches are scored like a fuzzy match of the same text, so that a match at the start of a word wins
+            _ => self.exact_position(chars).map(|_| matcher.fuzzy_match(text, &self.pattern()).unwrap_or(0)),
+        };
+
+        return match (score, self.negated) {
+            (Some(_), true) => None,
+            (None, true) => Some(0),
+            (score, false) => score,
+        };
+    }
+
+    fn indices(&self, text: &str, chars: &[char], matcher: &SkimMatcherV2) -> Vec<usize> {
+        if self.negated {
+            return vec![];
+        }
+
+        return match self.kind {
+            TermKind::Fuzzy => matcher.fuzzy_indices(text, &self.pattern()).map_or(vec![], |(_, indices)| indices),
+            _ => self.exact_position(chars).map_or(vec![], |start| (start..start + self.text.len()).collect()),
+        };
+    }
+
+    /// The char index where the exact kinds of terms match, ignoring the case.
+    fn exact_position(&self, chars: &[char]) -> Option<usize> {
+        if self.text.len() > chars.len() {
+            return None;
+        }
+
+        let last_start = chars.len() - self.text.len();
+        let matches_at = |start: usize| chars[start..start + self.text.len()].iter()
+            .zip(&self.text)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()));
+
+        return match self.kind {
+            TermKind::Prefix => Some(0).filter(|s| matches_at(*s)),
+            TermKind::Suffix => Some(last_start).filter(|s| matches_at(*s)),
+            TermKind::Equal => Some(0).filter(|s| last_start == 0 && matches_at(*s)),
+            _ => (0..=last_start).find(|s| matches_at(*s)),
+        };
+    }
+
+    fn pattern(&self) -> String {
+        return self.text.iter().collect();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PredictionTier {
+    Regular,
+    Pinned,
+    Alias,
+}
+
+#[derive(Clone)]
+pub struct Prediction<T> {
+    pub item: T,
+    pub score: i64,
+    tier: PredictionTier,
+}
+
+impl<T> Prediction<T> {
+    pub fn is_alias_match(&self) -> bool {
+        return self.tier == PredictionTier::Alias;
+    }
+}
+
+impl<T> Display for Prediction<T> where T: Display {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}", self.item))
+    }
+}
+
+impl<T> Eq for Prediction<T> {}
+
+impl<T> PartialEq<Self> for Prediction<T> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.tier.eq(&other.tier) && self.score.eq(&other.score);
+    }
+}
+
+impl<T> PartialOrd<Self> for Prediction<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl<T> Ord for Prediction<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self.tier.cmp(&other.tier).then(self.score.cmp(&other.score));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shown with a pin and tags around the path, like a pinned project.
+    #[derive(Clone, PartialEq, Eq)]
+    struct Project {
+        path: &'static str,
+        tags: Vec<&'static str>,
+    }
+
+    impl Display for Project {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            let tags = self.tags.iter().map(|t| format!(" #{t}")).collect::<String>();
+            write!(f, "📌 {} [rust]{tags}", self.path)
+        }
+    }
+
+    impl DialogueItem for Project {
+        fn tags(&self) -> Vec<&str> {
+            self.tags.clone()
+        }
+
+        fn search_text(&self) -> String {
+            self.path.to_string()
+        }
+    }
+
+    fn projects() -> Vec<Project> {
+        return vec![
+            Project { path: r"c:\work\api-gateway", tags: vec!["payments"] },
+            Project { path: r"c:\work\Web-App", tags: vec![] },
+            Project { path: r"c:\tmp\api", tags: vec![] },
+            Project { path: r"c:\Проекты\сайт", tags: vec![] },
+        ];
+    }
+
+    fn search(input: &str) -> Vec<&'static str> {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let mut paths = rank(&projects(), &Query::parse(input), &matcher, usize::MAX).into_iter()
+            .map(|p| p.item.path)
+            .collect::<Vec<_>>();
+        paths.sort();
+        return paths;
+    }
+
+    fn term(token: &str) -> Term {
+        return Term::parse(token).unwrap();
+    }
+
+    fn score(token: &str, text: &str) -> Option<i64> {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let chars = text.chars().collect::<Vec<_>>();
+        return term(token).score(text, &chars, &matcher);
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        let cases = [
+            ("api", TermKind::Fuzzy, false, "api"),
+            ("'api", TermKind::Exact, false, "api"),
+            ("^c:", TermKind::Prefix, false, "c:"),
+            ("app$", TermKind::Suffix, false, "app"),
+            ("^api$", TermKind::Equal, false, "api"),
+            ("!test", TermKind::Exact, true, "test"),
+            ("!'test", TermKind::Fuzzy, true, "test"),
+            ("!^c:", TermKind::Prefix, true, "c:"),
+            ("!app$", TermKind::Suffix, true, "app"),
+        ];
+
+        for (token, kind, negated, text) in cases {
+            let term = term(token);
+            assert!(term.kind == kind, "{token}");
+            assert_eq!(term.negated, negated, "{token}");
+            assert_eq!(term.pattern(), text, "{token}");
+        }
+    }
+
+    #[test]
+    fn lone_modifiers_are_ignored() {
+        for token in ["!", "'", "^", "$", "^$", "!'", "!^"] {
+            assert!(Term::parse(token).is_none(), "{token}");
+        }
+
+        let query = Query::parse("! ^ api $");
+        assert_eq!(query.groups.len(), 1);
+    }
+
+    #[test]
+    fn parses_or_groups_and_tags() {
+        let query = Query::parse("#Payments api | web !test | #rust");
+        assert_eq!(query.tags, vec!["payments", "rust"]);
+        assert_eq!(query.groups.len(), 2);
+        assert_eq!(query.groups[0].iter().map(|t| t.pattern()).collect::<Vec<_>>(), vec!["api", "web"]);
+        assert_eq!(query.groups[1].iter().map(|t| t.pattern()).collect::<Vec<_>>(), vec!["test"]);
+    }
+
+    #[test]
+    fn leading_or_starts_a_group() {
+        let query = Query::parse("| api");
+        assert_eq!(query.groups.len(), 1);
+        assert_eq!(query.groups[0].len(), 1);
+    }
+
+    #[test]
+    fn scores_terms() {
+        assert!(score("gtw", r"c:\work\api-gateway").is_some());
+        assert!(score("'gtw", r"c:\work\api-gateway").is_none());
+        assert!(score("'GATE", r"c:\work\api-gateway").is_some());
+        assert!(score(r"^C:\WORK", r"c:\work\api-gateway").is_some());
+        assert!(score("^work", r"c:\work\api-gateway").is_none());
+        assert!(score("way$", r"c:\work\api-gateway").is_some());
+        assert!(score("api$", r"c:\work\api-gateway").is_none());
+        assert!(score("^api$", "API").is_some());
+        assert!(score("^api$", "apis").is_none());
+        assert_eq!(score("!test", r"c:\work\api-gateway"), Some(0));
+        assert!(score("!gate", r"c:\work\api-gateway").is_none());
+        assert!(score("!'gtw", r"c:\work\api-gateway").is_none());
+        assert!(score("'ПРОЕКТ", r"c:\Проекты").is_some());
+    }
+
+    #[test]
+    fn exact_match_scores_like_fuzzy() {
+        let exact = score("'gate", r"c:\work\api-gateway").unwrap();
+        let fuzzy = score("gate", r"c:\work\api-gateway").unwrap();
+        assert_eq!(exact, fuzzy);
+    }
+
+    #[test]
+    fn all_groups_have_to_match() {
+        assert_eq!(search(""), vec![r"c:\tmp\api", r"c:\work\Web-App", r"c:\work\api-gateway", r"c:\Проекты\сайт"]);
+        assert_eq!(search("'api"), vec![r"c:\tmp\api", r"c:\work\api-gateway"]);
+        assert_eq!(search("api !gateway"), vec![r"c:\tmp\api"]);
+        assert_eq!(search("work app$ | gateway$ !web"), vec![r"c:\work\api-gateway"]);
+        assert_eq!(search("app$ | gateway$"), vec![r"c:\work\Web-App", r"c:\work\api-gateway"]);
+    }
+
+    #[test]
+    fn anchors_ignore_the_decorations() {
+        assert_eq!(search(r"^c:\work"), vec![r"c:\work\Web-App", r"c:\work\api-gateway"]);
+        assert_eq!(search("gateway$"), vec![r"c:\work\api-gateway"]);
+        assert_eq!(search(r"^c:\tmp\api$"), vec![r"c:\tmp\api"]);
+        assert!(search("rust$").is_empty());
+    }
+
+    #[test]
+    fn tags_filter_together_with_terms() {
+        assert_eq!(search("#pay api"), vec![r"c:\work\api-gateway"]);
+        assert!(search("#pay web").is_empty());
+    }
+
+    #[test]
+    fn highlights_the_matched_chars() {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        assert_eq!(Query::parse("'ект !x").matched_indices(r"c:\Проекты", &matcher), vec![6, 7, 8]);
+        assert_eq!(Query::parse("^c: way$").matched_indices("c:gateway", &matcher), vec![0, 1, 6, 7, 8]);
+        assert!(Query::parse("!c").matched_indices("abc", &matcher).is_empty());
+    }
+}
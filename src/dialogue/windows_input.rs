@@ -2,8 +2,9 @@ use std::{io, mem};
 use dialoguer::console::Key;
 use encode_unicode::CharExt;
 use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
-use windows_sys::Win32::System::Console::{GetNumberOfConsoleInputEvents, GetStdHandle, INPUT_RECORD, KEY_EVENT, KEY_EVENT_RECORD, ReadConsoleInputW, STD_INPUT_HANDLE};
-use windows_sys::Win32::UI::Input::KeyboardAndMouse::VIRTUAL_KEY;
+use windows_sys::Win32::System::Console::{GetNumberOfConsoleInputEvents, GetStdHandle, INPUT_RECORD, KEY_EVENT, KEY_EVENT_RECORD, PeekConsoleInputW, ReadConsoleInputW, STD_INPUT_HANDLE};
+use windows_sys::Win32::System::Console::{LEFT_ALT_PRESSED, LEFT_CTRL_PRESSED, RIGHT_ALT_PRESSED, RIGHT_CTRL_PRESSED};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{VIRTUAL_KEY, VK_BACK};
 
 pub fn try_read_single_key() -> io::Result<Option<Key>> {
     if get_key_event_count()? == 0 {
@@ -17,9 +18,16 @@ pub fn try_read_single_key() -> io::Result<Option<Key>> {
         None => return Ok(None)
     };
 
+    let ctrl_pressed = key_event.dwControlKeyState & (LEFT_CTRL_PRESSED | RIGHT_CTRL_PRESSED) != 0;
+    let alt_pressed = key_event.dwControlKeyState & (LEFT_ALT_PRESSED | RIGHT_ALT_PRESSED) != 0;
+    if key_event.wVirtualKeyCode == VK_BACK && (ctrl_pressed || alt_pressed) {
+        return Ok(Some(alt_backspace()));
+    }
+
     let unicode_char = unsafe { key_event.uChar.UnicodeChar };
     if unicode_char == 0 {
-        Ok(Some(key_from_key_code(key_event.wVirtualKeyCode)))
+        let key = key_from_key_code(key_event.wVirtualKeyCode);
+        Ok(Some(if ctrl_pressed { with_ctrl(key) } else { key }))
     } else {
         // This is a unicode character, in utf-16. Try to decode it by itself.
         match char::from_utf16_tuple((unicode_char, None)) {
@@ -32,6 +40,8 @@ pub fn try_read_single_key() -> io::Result<Option<Key>> {
                     Ok(Some(Key::Backspace))
                 } else if c == '\x1B' {
                     Ok(Some(Key::Escape))
+                } else if c == '\t' {
+                    Ok(Some(Key::Tab))
                 } else {
                     Ok(Some(Key::Char(c)))
                 }
@@ -82,6 +92,92 @@ pub fn try_read_single_key() -> io::Result<Option<Key>> {
     }
 }
 
+/// Reads the text that is already waiting in the console input, e.g. the rest of a paste.
+/// Line breaks and tabs only count as text when more text follows them, so that an Enter pressed right after
+/// typing is left for `try_read_single_key`, like every other key that is not text.
+pub fn read_pending_text() -> io::Result<String> {
+    let pending = peek_pending_events()?;
+
+    let mut text_events = 0;
+    for (idx, event) in pending.iter().enumerate() {
+        match pending_key(event) {
+            PendingKey::Text => text_events = idx + 1,
+            PendingKey::LineBreak | PendingKey::Skipped => {}
+            PendingKey::Other => break,
+        }
+    }
+
+    let mut text = String::new();
+    let mut read_events = 0;
+    while read_events < text_events {
+        // Key releases and other events are read and skipped
+        let key = try_read_single_key()?;
+        read_events += match &key {
+            // Characters outside of the BMP arrive as two events, one for each half of the surrogate pair
+            Some(Key::Char(c)) => c.len_utf16(),
+            _ => 1,
+        };
+
+        match key {
+            Some(Key::Char(c)) => text.push(c),
+            Some(Key::Enter) => text.push('\n'),
+            Some(Key::Tab) => text.push('\t'),
+            _ => {}
+        }
+    }
+
+    return Ok(text);
+}
+
+enum PendingKey {
+    Text,
+    /// Enter or Tab, text only in the middle of a paste
+    LineBreak,
+    /// A key that is not text, the pending text ends here
+    Other,
+    /// Key releases and events that are not keys
+    Skipped,
+}
+
+fn pending_key(event: &INPUT_RECORD) -> PendingKey {
+    if event.EventType != KEY_EVENT as u16 {
+        return PendingKey::Skipped;
+    }
+
+    let key_event: KEY_EVENT_RECORD = unsafe { event.Event.KeyEvent };
+    if key_event.bKeyDown == 0 {
+        return PendingKey::Skipped;
+    }
+
+    let unicode_char = unsafe { key_event.uChar.UnicodeChar };
+    return match char::from_u32(unicode_char as u32) {
+        Some('\r' | '\n' | '\t') => PendingKey::LineBreak,
+        // Keys without a character, like the arrows, have 0 here
+        Some(c) if c.is_control() => PendingKey::Other,
+        // Text, or half of a surrogate pair
+        _ => PendingKey::Text,
+    };
+}
+
+/// The events in the console input, without removing them from it.
+fn peek_pending_events() -> io::Result<Vec<INPUT_RECORD>> {
+    let handle = get_stdin_handle()?;
+    let event_count = get_key_event_count()?;
+    if event_count == 0 {
+        return Ok(vec![]);
+    }
+
+    let mut buffer: Vec<INPUT_RECORD> = (0..event_count).map(|_| unsafe { mem::zeroed() }).collect();
+    let mut events_read: u32 = 0;
+    let success = unsafe { PeekConsoleInputW(handle, buffer.as_mut_ptr(), event_count, &mut events_read) };
+    if success == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    buffer.truncate(events_read as usize);
+    return Ok(buffer);
+}
+
 fn try_read_key_event() -> io::Result<Option<KEY_EVENT_RECORD>> {
     let handle = get_stdin_handle()?;
     let mut buffer: INPUT_RECORD = unsafe { mem::zeroed() };
@@ -138,6 +234,23 @@ fn get_key_event_count() -> io::Result<u32> {
     }
 }
 
+/// Key has no modifiers, so Ctrl with a key is reported as the escape sequence that terminals send for it.
+/// Keys without such a sequence are left as they are.
+pub fn with_ctrl(key: Key) -> Key {
+    let final_char = match key {
+        Key::ArrowRight => 'C',
+        Key::ArrowLeft => 'D',
+        Key::Home => 'H',
+        Key::End => 'F',
+        key => return key,
+    };
+    return Key::UnknownEscSeq(vec!['[', '1', ';', '5', final_char]);
+}
+
+/// Alt+Backspace (and Ctrl+Backspace), as terminals send it.
+pub fn alt_backspace() -> Key {
+    return Key::UnknownEscSeq(vec!['\x7f']);
+}
 
 pub fn key_from_key_code(code: VIRTUAL_KEY) -> Key {
     use windows_sys::Win32::UI::Input::KeyboardAndMouse;
@@ -152,6 +265,8 @@ pub fn key_from_key_code(code: VIRTUAL_KEY) -> Key {
         KeyboardAndMouse::VK_BACK => Key::Backspace,
         KeyboardAndMouse::VK_TAB => Key::Tab,
         KeyboardAndMouse::VK_HOME => Key::Home,
+        KeyboardAndMouse::VK_PRIOR => Key::PageUp,
+        KeyboardAndMouse::VK_NEXT => Key::PageDown,
         KeyboardAndMouse::VK_END => Key::End,
         KeyboardAndMouse::VK_DELETE => Key::Del,
         KeyboardAndMouse::VK_SHIFT => Key::Shift,
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_data::{self, is_same_path};
+use crate::repos::to_full_path;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const FILE_NAME: &str = "favorites.json";
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Favorites {
+    pub pinned: Vec<PinnedProject>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PinnedProject {
+    pub path: String,
+    pub alias: Option<String>,
+}
+
+pub fn load() -> Favorites {
+    // A broken favorites file should not prevent the picker from opening
+    return app_data::read_json::<Favorites>(FILE_NAME).unwrap_or_default();
+}
+
+pub fn pin(path: &str, alias: Option<String>) -> Result<()> {
+    let full_path = to_full_path(&PathBuf::from(path));
+    if !PathBuf::from(&full_path).is_dir() {
+        return Err(format!("{full_path} is not a directory").into());
+    }
+
+    let mut favorites = app_data::read_json::<Favorites>(FILE_NAME)?;
+
+    if let Some(alias) = &alias {
+        let alias_taken = favorites.pinned.iter()
+            .any(|p| is_same_alias(p, alias) && !is_same_path(&p.path, &full_path));
+        if alias_taken {
+            return Err(format!("Alias '{alias}' is already used by another project").into());
+        }
+    }
+
+    match favorites.pinned.iter_mut().find(|p| is_same_path(&p.path, &full_path)) {
+        Some(existing) => {
+            existing.alias = alias;
+        }
+        None => {
+            favorites.pinned.push(PinnedProject {
+                path: full_path,
+                alias,
+            });
+        }
+    }
+
+    return app_data::write_json(FILE_NAME, &favorites);
+}
+
+/// Removes a pinned project, either by its alias or by its path.
+pub fn unpin(path_or_alias: &str) -> Result<()> {
+    let mut favorites = app_data::read_json::<Favorites>(FILE_NAME)?;
+    let full_path = to_full_path(&PathBuf::from(path_or_alias));
+
+    let position = favorites.pinned.iter()
+        .position(|p| is_same_alias(p, path_or_alias))
+        .or_else(|| favorites.pinned.iter().position(|p| is_same_path(&p.path, &full_path)));
+
+    match position {
+        Some(position) => {
+            favorites.pinned.remove(position);
+            return app_data::write_json(FILE_NAME, &favorites);
+        }
+        None => Err(format!("{path_or_alias} is not pinned").into()),
+    }
+}
+
+/// Aliases are typed into the picker, which matches them regardless of the case.
+fn is_same_alias(pinned: &PinnedProject, alias: &str) -> bool {
+    return pinned.alias.as_ref().is_some_and(|a| a.eq_ignore_ascii_case(alias));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::empty_data_folder;
+
+    #[test]
+    fn aliases_ignore_the_case() {
+        let (_guard, data_folder) = empty_data_folder();
+        let api = data_folder.join("api");
+        let web = data_folder.join("web");
+        std::fs::create_dir_all(&api).unwrap();
+        std::fs::create_dir_all(&web).unwrap();
+
+        pin(api.to_str().unwrap(), Some("api".to_string())).unwrap();
+        assert!(pin(web.to_str().unwrap(), Some("API".to_string())).is_err());
+
+        unpin("Api").unwrap();
+        assert!(load().pinned.is_empty());
+    }
+}
@@ -0,0 +1,435 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_data::is_same_path;
+use crate::config;
+use crate::repos::to_full_path;
+use crate::store::LockedStore;
+use crate::visit_log;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const HISTORY_FILE_NAME: &str = "directory_history.json";
+
+/// The history with the pending visits from the log folded in.
+pub fn load() -> Result<DirectoryHistory> {
+    if visit_log::has_visits() {
+        return compact();
+    }
+
+    let mut store = LockedStore::lock_shared(HISTORY_FILE_NAME)?;
+    let mut history = store.read::<DirectoryHistory>()?;
+
+    // A freshly added exclusion hides the entries right away, they are removed on the next write
+    let exclusions = Exclusions::load()?;
+    history.remove_where(|d| exclusions.is_excluded(d));
+    return Ok(history);
+}
+
+/// Folds the visits from the log into the history.
+pub fn compact() -> Result<DirectoryHistory> {
+    return modify(|history| history.clone());
+}
+
+/// Changes the history under the lock, after folding in the visits from the log.
+pub fn modify<R>(change: impl FnOnce(&mut DirectoryHistory) -> R) -> Result<R> {
+    let mut store = LockedStore::lock_exclusive(HISTORY_FILE_NAME)?;
+    let mut history = store.read::<DirectoryHistory>()?;
+
+    let compaction = visit_log::start_compaction(history.compacted_log_hash.as_deref())?;
+    history.add_visits(compaction.visits);
+    if compaction.log_hash.is_some() {
+        history.compacted_log_hash = compaction.log_hash;
+    }
+
+    let result = change(&mut history);
+
+    let exclusions = Exclusions::load()?;
+    history.remove_where(|d| exclusions.is_excluded(d));
+    history.age();
+
+    store.write(&history)?;
+    visit_log::finish_compaction()?;
+    return Ok(result);
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SortOrder {
+    Frecency,
+    Recent,
+    Count,
+}
+
+#[derive(Serialize)]
+struct ListedDir<'a> {
+    #[serde(flatten)]
+    dir: &'a VisitedDir,
+    frecency: f64,
+}
+
+/// Prints `frecency\tcount\tpath` for every visited directory, or a json array with the same data.
+pub fn list(json: bool, sort: SortOrder) -> Result<()> {
+    let now = now_timestamp()?;
+    let mut dirs = load()?.visited_dirs;
+    match sort {
+        SortOrder::Frecency => dirs.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now))),
+        SortOrder::Recent => dirs.sort_by_key(|d| Reverse(d.last_accessed_timestamp)),
+        SortOrder::Count => dirs.sort_by(|a, b| b.times.total_cmp(&a.times)),
+    }
+
+    if json {
+        let listed = dirs.iter()
+            .map(|d| ListedDir { dir: d, frecency: d.frecency(now) })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+
+    for dir in &dirs {
+        println!("{:.1}\t{:.1}\t{}", dir.frecency(now), dir.times, dir.dir);
+    }
+    return Ok(());
+}
+
+/// Records a visit to the directory, as if it was opened in the shell.
+pub fn add(dir: &str) -> Result<()> {
+    let full_path = to_full_path(&PathBuf::from(dir));
+    if !Path::new(&full_path).is_dir() {
+        return Err(format!("{full_path} is not a directory").into());
+    }
+
+    if Exclusions::load()?.is_excluded(&full_path) {
+        return Err(format!("{full_path} is excluded from the history by history.exclude in the config").into());
+    }
+
+    let now = now_timestamp()?;
+    return modify(|history| history.add_visit(full_path, now));
+}
+
+/// Removes a directory, or all the directories matching a glob like `C:\temp\**`.
+pub fn remove(dir_or_pattern: &str) -> Result<()> {
+    let is_pattern = dir_or_pattern.contains(['*', '?', '[']);
+    let removed = if is_pattern {
+        let pattern = glob::Pattern::new(dir_or_pattern)
+            .map_err(|e| format!("Invalid pattern '{dir_or_pattern}': {e}"))?;
+        modify(|history| history.remove_where(|d| matches_pattern(&pattern, d)))?
+    } else {
+        let full_path = to_full_path(&PathBuf::from(dir_or_pattern));
+        modify(|history| history.remove_where(|d| is_same_path(d, &full_path)))?
+    };
+
+    if removed == 0 {
+        return Err(format!("{dir_or_pattern} is not in the history").into());
+    }
+
+    println!("Removed {removed} directories");
+    return Ok(());
+}
+
+/// Removes the directories that do not exist anymore, including the ones on drives that are not available.
+pub fn prune() -> Result<()> {
+    let removed = modify(|history| history.remove_where(|d| !Path::new(d).is_dir()))?;
+    println!("Removed {removed} directories");
+    return Ok(());
+}
+
+/// Forgets every directory without reading the old history, so that a broken one can be cleared too.
+pub fn clear() -> Result<()> {
+    let store = LockedStore::lock_exclusive(HISTORY_FILE_NAME)?;
+
+    // The visits that were not folded into the history yet go as well
+    visit_log::finish_compaction()?;
+    visit_log::start_compaction(None)?;
+
+    store.write(&DirectoryHistory::default())?;
+    visit_log::finish_compaction()?;
+    println!("Cleared the history");
+    return Ok(());
+}
+
+/// Directories that are never recorded, from the [history] section of the config.
+pub struct Exclusions {
+    patterns: Vec<glob::Pattern>,
+    min_depth: usize,
+}
+
+impl Exclusions {
+    pub fn load() -> Result<Exclusions> {
+        let history_config = &config::get().history;
+
+        let mut patterns = vec![];
+        for exclude in &history_config.exclude {
+            // A variable that is not set leaves nothing to exclude, like $TEMP outside of windows
+            let Ok(expanded) = shellexpand::full(exclude) else {
+                continue;
+            };
+            let pattern = glob::Pattern::new(&expanded)
+                .map_err(|e| format!("Invalid history exclude pattern '{exclude}': {e}"))?;
+            patterns.push(pattern);
+        }
+
+        return Ok(Exclusions { patterns, min_depth: history_config.min_depth });
+    }
+
+    pub fn is_excluded(&self, dir: &str) -> bool {
+        let depth = Path::new(dir).components().filter(|c| matches!(c, Component::Normal(_))).count();
+        return depth < self.min_depth || self.patterns.iter().any(|p| matches_pattern(p, dir));
+    }
+}
+
+/// Case-insensitive, and a single `*` stays inside of one path segment.
+pub fn matches_pattern(pattern: &glob::Pattern, dir: &str) -> bool {
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    return pattern.matches_with(dir, options);
+}
+
+pub fn now_timestamp() -> Result<u64> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    return Ok(now);
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct DirectoryHistory {
+    pub visited_dirs: Vec<VisitedDir>,
+    /// The visit log that was folded in last, written in the same file so that a crash can not count it twice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compacted_log_hash: Option<String>,
+}
+
+impl DirectoryHistory {
+    pub fn add_visit(&mut self, dir: String, timestamp: u64) {
+        match self.visited_dirs.iter_mut().find(|d| d.dir == dir) {
+            Some(existing_dir) => {
+                // Visits are compacted in order, but the newest timestamp should win either way
+                existing_dir.last_accessed_timestamp = existing_dir.last_accessed_timestamp.max(timestamp);
+                existing_dir.times += 1.0;
+            }
+            None => {
+                self.visited_dirs.push(VisitedDir {
+                    dir,
+                    last_accessed_timestamp: timestamp,
+                    times: 1.0,
+                });
+            }
+        }
+    }
+
+    /// Folds in the visits from the log, which can have thousands of them, without searching the history for each one.
+    pub fn add_visits(&mut self, visits: Vec<visit_log::Visit>) {
+        let mut positions = self.visited_dirs.iter().enumerate()
+            .map(|(idx, d)| (d.dir.clone(), idx))
+            .collect::<HashMap<_, _>>();
+
+        for visit in visits {
+            match positions.get(&visit.dir) {
+                Some(&idx) => {
+                    let existing_dir = &mut self.visited_dirs[idx];
+                    existing_dir.last_accessed_timestamp = existing_dir.last_accessed_timestamp.max(visit.timestamp);
+                    existing_dir.times += 1.0;
+                }
+                None => {
+                    positions.insert(visit.dir.clone(), self.visited_dirs.len());
+                    self.visited_dirs.push(VisitedDir {
+                        dir: visit.dir,
+                        last_accessed_timestamp: visit.timestamp,
+                        times: 1.0,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns how many directories were removed.
+    fn remove_where(&mut self, predicate: impl Fn(&str) -> bool) -> usize {
+        let count = self.visited_dirs.len();
+        self.visited_dirs.retain(|d| !predicate(&d.dir));
+        return count - self.visited_dirs.len();
+    }
+
+    /// Once the visits add up to more than history.max_visits, all of them are scaled down,
+    /// so that old favourites fade away and the ones that drop below a single visit are forgotten.
+    fn age(&mut self) {
+        let max_total_times = config::get().history.max_visits;
+        let aged_total_times = max_total_times * 0.9;
+
+        let total_times = self.visited_dirs.iter().map(|d| d.times).sum::<f64>();
+        if total_times <= max_total_times {
+            return;
+        }
+
+        let factor = aged_total_times / total_times;
+        for dir in &mut self.visited_dirs {
+            dir.times *= factor;
+        }
+        self.visited_dirs.retain(|d| d.times >= 1.0);
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VisitedDir {
+    pub dir: String,
+    pub last_accessed_timestamp: u64,
+    /// Number of visits, fractional once the history has been aged
+    pub times: f64,
+}
+
+impl VisitedDir {
+    /// Visit count weighted by how recently the directory was visited, like zoxide does it.
+    pub fn frecency(&self, now: u64) -> f64 {
+        const HOUR: u64 = 60 * 60;
+        const DAY: u64 = 24 * HOUR;
+        const WEEK: u64 = 7 * DAY;
+
+        let since_last_access = now.saturating_sub(self.last_accessed_timestamp);
+        let weight = match since_last_access {
+            s if s < HOUR => 4.0,
+            s if s < DAY => 2.0,
+            s if s < WEEK => 0.5,
+            _ => 0.25,
+        };
+        return self.times * weight;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn times(history: &DirectoryHistory, dir: &str) -> f64 {
+        return history.visited_dirs.iter().find(|d| d.dir == dir).map_or(0.0, |d| d.times);
+    }
+
+    fn dir(dir: &str, last_accessed_timestamp: u64, times: f64) -> VisitedDir {
+        return VisitedDir { dir: dir.to_string(), last_accessed_timestamp, times };
+    }
+
+    #[test]
+    fn frecency_weighs_recent_visits_more() {
+        const NOW: u64 = 1_700_000_000;
+        const HOUR: u64 = 60 * 60;
+        let visited = |ago: u64| dir(r"C:\work\api", NOW - ago, 10.0);
+
+        assert_eq!(visited(0).frecency(NOW), 40.0);
+        assert_eq!(visited(HOUR - 1).frecency(NOW), 40.0);
+        assert_eq!(visited(HOUR).frecency(NOW), 20.0);
+        assert_eq!(visited(24 * HOUR - 1).frecency(NOW), 20.0);
+        assert_eq!(visited(24 * HOUR).frecency(NOW), 5.0);
+        assert_eq!(visited(7 * 24 * HOUR - 1).frecency(NOW), 5.0);
+        assert_eq!(visited(7 * 24 * HOUR).frecency(NOW), 2.5);
+        assert_eq!(visited(365 * 24 * HOUR).frecency(NOW), 2.5);
+    }
+
+    #[test]
+    fn frecency_of_a_visit_from_the_future() {
+        // The clock was turned back since the visit
+        assert_eq!(dir(r"C:\work\api", 2_000, 3.0).frecency(1_000), 12.0);
+    }
+
+    #[test]
+    fn history_under_max_visits_is_not_aged() {
+        let max_visits = config::get().history.max_visits;
+        let mut history = DirectoryHistory {
+            visited_dirs: vec![dir(r"C:\work\api", 1, max_visits - 1.0), dir(r"C:\work\web", 2, 1.0)],
+            ..DirectoryHistory::default()
+        };
+
+        history.age();
+        assert_eq!(times(&history, r"C:\work\api"), max_visits - 1.0);
+        assert_eq!(times(&history, r"C:\work\web"), 1.0);
+    }
+
+    #[test]
+    fn aging_scales_to_nine_tenths_and_forgets_rare_dirs() {
+        let max_visits = config::get().history.max_visits;
+        let mut history = DirectoryHistory {
+            visited_dirs: vec![
+                dir(r"C:\work\api", 1, max_visits * 0.75),
+                dir(r"C:\work\web", 2, max_visits * 0.25),
+                dir(r"C:\work\once", 3, 1.0),
+            ],
+            ..DirectoryHistory::default()
+        };
+
+        history.age();
+        let total = max_visits + 1.0;
+        assert!((times(&history, r"C:\work\api") - max_visits * 0.75 * max_visits * 0.9 / total).abs() < 1e-6);
+        assert!((times(&history, r"C:\work\web") - max_visits * 0.25 * max_visits * 0.9 / total).abs() < 1e-6);
+        // 0.9 of a visit is dropped
+        assert_eq!(history.visited_dirs.len(), 2);
+        assert!(history.visited_dirs.iter().map(|d| d.times).sum::<f64>() < max_visits * 0.9);
+    }
+
+    #[test]
+    fn compaction_folds_the_log_once() {
+        let (_guard, _) = test_support::empty_data_folder();
+        visit_log::append(1, r"C:\work\api").unwrap();
+        visit_log::append(2, r"C:\work\api").unwrap();
+
+        let history = compact().unwrap();
+        assert_eq!(times(&history, r"C:\work\api"), 2.0);
+        assert!(!visit_log::has_visits());
+
+        let history = compact().unwrap();
+        assert_eq!(times(&history, r"C:\work\api"), 2.0);
+    }
+
+    /// The history is saved, but the process dies before the folded log is removed.
+    #[test]
+    fn compaction_that_did_not_finish_is_not_counted_twice() {
+        let (_guard, _) = test_support::empty_data_folder();
+        visit_log::append(1, r"C:\work\api").unwrap();
+        visit_log::append(2, r"C:\work\api").unwrap();
+        {
+            let store = LockedStore::lock_exclusive(HISTORY_FILE_NAME).unwrap();
+            let mut history = DirectoryHistory::default();
+            let compaction = visit_log::start_compaction(None).unwrap();
+            history.add_visits(compaction.visits);
+            history.compacted_log_hash = compaction.log_hash;
+            store.write(&history).unwrap();
+        }
+        visit_log::append(3, r"C:\work\web").unwrap();
+
+        let history = compact().unwrap();
+        assert_eq!(times(&history, r"C:\work\api"), 2.0);
+        assert_eq!(times(&history, r"C:\work\web"), 1.0);
+        assert!(!visit_log::has_visits());
+    }
+
+    /// The log is moved aside, but the process dies before the history is saved.
+    #[test]
+    fn compaction_that_did_not_save_is_retried() {
+        let (_guard, _) = test_support::empty_data_folder();
+        visit_log::append(1, r"C:\work\api").unwrap();
+        visit_log::start_compaction(None).unwrap();
+        visit_log::append(2, r"C:\work\web").unwrap();
+
+        let history = compact().unwrap();
+        assert_eq!(times(&history, r"C:\work\api"), 1.0);
+        assert_eq!(times(&history, r"C:\work\web"), 0.0);
+
+        let history = compact().unwrap();
+        assert_eq!(times(&history, r"C:\work\web"), 1.0);
+    }
+
+    #[test]
+    fn clear_does_not_read_the_old_history() {
+        let (_guard, data_folder) = test_support::empty_data_folder();
+        std::fs::write(data_folder.join(HISTORY_FILE_NAME), "{\"visited_dirs\": [").unwrap();
+        visit_log::append(1, r"C:\work\api").unwrap();
+
+        clear().unwrap();
+        assert!(!visit_log::has_visits());
+        assert!(load().unwrap().visited_dirs.is_empty());
+        assert!(!data_folder.join("directory_history.json.broken").exists());
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+
+use crate::history;
+use crate::history::VisitedDir;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+/// Tools with a directory database that can be imported into the wcd history.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ImportSource {
+    Zoxide,
+    Z,
+    Autojump,
+    Fasd,
+}
+
+impl ImportSource {
+    /// Where the tool keeps its database unless told otherwise.
+    fn default_path(&self) -> Option<PathBuf> {
+        let env_path = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+
+        return match self {
+            ImportSource::Zoxide => env_path("_ZO_DATA_DIR")
+                .or_else(|| dirs::data_local_dir().map(|d| d.join("zoxide")))
+                .map(|d| d.join("db.zo")),
+            ImportSource::Z => env_path("_Z_DATA").or_else(|| dirs::home_dir().map(|d| d.join(".z"))),
+            ImportSource::Autojump => dirs::data_dir().map(|d| d.join("autojump").join("autojump.txt")),
+            ImportSource::Fasd => env_path("_FASD_DATA").or_else(|| dirs::home_dir().map(|d| d.join(".fasd"))),
+        };
+    }
+}
+
+/// Adds the directories from the database of another tool to the history.
+/// Directories that are already known get the imported visits on top of their own.
+pub fn import(source: ImportSource, file: Option<String>) -> Result<()> {
+    let path = match file.map(PathBuf::from).or_else(|| source.default_path()) {
+        Some(path) => path,
+        None => return Err("Could not find the database, pass its path".into()),
+    };
+
+    let content = std::fs::read(&path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+
+    let imported = match source {
+        ImportSource::Zoxide => parse_zoxide(&content),
+        ImportSource::Z | ImportSource::Fasd => parse_z(&String::from_utf8_lossy(&content)),
+        ImportSource::Autojump => parse_autojump(&String::from_utf8_lossy(&content), modified_timestamp(&path)?),
+    }.map_err(|e| format!("Could not parse {}: {e}", path.display()))?;
+
+    let count = imported.len();
+    history::modify(|history| {
+        for dir in imported {
+            match history.visited_dirs.iter_mut().find(|d| d.dir == dir.dir) {
+                Some(existing_dir) => {
+                    existing_dir.times += dir.times;
+                    existing_dir.last_accessed_timestamp = existing_dir.last_accessed_timestamp.max(dir.last_accessed_timestamp);
+                }
+                None => history.visited_dirs.push(dir),
+            }
+        }
+    })?;
+
+    println!("Imported {count} directories from {}", path.display());
+    return Ok(());
+}
+
+/// zoxide keeps a bincode encoded `(u32 version, Vec<Dir>)`,
+/// where a dir is a length prefixed path, the rank as f64 and the last access as u64, all little endian.
+fn parse_zoxide(content: &[u8]) -> Result<Vec<VisitedDir>> {
+    const SUPPORTED_VERSION: u32 = 3;
+
+    let mut reader = BinaryReader { content, position: 0 };
+    let version = u32::from_le_bytes(reader.take(4)?.try_into()?);
+    if version != SUPPORTED_VERSION {
+        return Err(format!("unsupported zoxide database version {version}").into());
+    }
+
+    let count = reader.read_u64()?;
+    let mut dirs = vec![];
+    for _ in 0..count {
+        let path_length = reader.read_u64()? as usize;
+        let dir = std::str::from_utf8(reader.take(path_length)?)?.to_string();
+        let rank = f64::from_le_bytes(reader.take(8)?.try_into()?);
+        let last_accessed_timestamp = reader.read_u64()?;
+
+        // The rank grows by one with every visit, the same as our visit count
+        dirs.push(VisitedDir { dir, last_accessed_timestamp, times: rank });
+    }
+
+    return Ok(dirs);
+}
+
+struct BinaryReader<'a> {
+    content: &'a [u8],
+    position: usize,
+}
+
+impl BinaryReader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8]> {
+        let end = self.position.checked_add(length)
+            .filter(|end| *end <= self.content.len())
+            .ok_or("unexpected end of file")?;
+
+        let bytes = &self.content[self.position..end];
+        self.position = end;
+        return Ok(bytes);
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        return Ok(u64::from_le_bytes(self.take(8)?.try_into()?));
+    }
+}
+
+/// z and fasd write a `path|rank|timestamp` line per directory, the rank grows by about one with every visit.
+fn parse_z(content: &str) -> Result<Vec<VisitedDir>> {
+    let dirs = content.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            // The path can contain the separator itself, the numbers can not
+            let mut parts = line.rsplitn(3, '|');
+            let timestamp = parts.next();
+            let rank = parts.next();
+            let dir = parts.next();
+
+            let (Some(dir), Some(rank), Some(timestamp)) = (dir, rank, timestamp) else {
+                return Err(format!("unexpected line '{line}'"));
+            };
+            let times = rank.trim().parse::<f64>().map_err(|e| format!("bad rank in '{line}': {e}"))?;
+            let last_accessed_timestamp = timestamp.trim().parse::<u64>().map_err(|e| format!("bad timestamp in '{line}': {e}"))?;
+
+            Ok(VisitedDir { dir: dir.to_string(), last_accessed_timestamp, times })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    return Ok(dirs);
+}
+
+/// autojump writes a `weight\tpath` line per directory and keeps no timestamps.
+fn parse_autojump(content: &str, timestamp: u64) -> Result<Vec<VisitedDir>> {
+    let dirs = content.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            let Some((weight, dir)) = line.split_once('\t') else {
+                return Err(format!("unexpected line '{line}'"));
+            };
+            let weight = weight.trim().parse::<f64>().map_err(|e| format!("bad weight in '{line}': {e}"))?;
+
+            // Every visit raises the weight to sqrt(weight^2 + 10^2), starting from 10, so this undoes it
+            let times = ((weight / 10.0) * (weight / 10.0)).max(1.0);
+            Ok(VisitedDir { dir: dir.to_string(), last_accessed_timestamp: timestamp, times })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    return Ok(dirs);
+}
+
+/// The closest thing to a last visit that autojump has.
+fn modified_timestamp(path: &Path) -> Result<u64> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    return Ok(modified.duration_since(std::time::UNIX_EPOCH)?.as_secs());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(dirs: &[VisitedDir]) -> Vec<(&str, f64, u64)> {
+        return dirs.iter().map(|d| (d.dir.as_str(), d.times, d.last_accessed_timestamp)).collect();
+    }
+
+    fn zoxide_db(version: u32, dirs: &[(&str, f64, u64)]) -> Vec<u8> {
+        let mut content = version.to_le_bytes().to_vec();
+        content.extend((dirs.len() as u64).to_le_bytes());
+        for (dir, rank, timestamp) in dirs {
+            content.extend((dir.len() as u64).to_le_bytes());
+            content.extend(dir.as_bytes());
+            content.extend(rank.to_le_bytes());
+            content.extend(timestamp.to_le_bytes());
+        }
+        return content;
+    }
+
+    #[test]
+    fn zoxide_database() {
+        let dirs = [(r"C:\work\api", 12.5, 1_700_000_000), (r"C:\work\проект", 1.0, 1_700_000_100)];
+        let content = zoxide_db(3, &dirs);
+
+        assert_eq!(summary(&parse_zoxide(&content).unwrap()), dirs);
+        assert!(parse_zoxide(&zoxide_db(3, &[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn zoxide_database_cut_short() {
+        let content = zoxide_db(3, &[(r"C:\work\api", 12.5, 1_700_000_000)]);
+        for length in [0, 2, 4, 11, 20, content.len() - 1] {
+            assert!(parse_zoxide(&content[..length]).is_err(), "{length} bytes were accepted");
+        }
+
+        // A count that does not fit the file
+        let mut content = zoxide_db(3, &[]);
+        content[4..12].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_zoxide(&content).is_err());
+    }
+
+    #[test]
+    fn zoxide_unsupported_version() {
+        let error = parse_zoxide(&zoxide_db(2, &[("/home", 1.0, 1)])).err().unwrap();
+        assert_eq!(error.to_string(), "unsupported zoxide database version 2");
+    }
+
+    #[test]
+    fn z_and_fasd_lines() {
+        let content = "/home/kostya/work|10.5|1700000000\n\n/tmp/a|b|c|2|1700000100\r\n";
+        assert_eq!(summary(&parse_z(content).unwrap()), [
+            ("/home/kostya/work", 10.5, 1_700_000_000),
+            ("/tmp/a|b|c", 2.0, 1_700_000_100),
+        ]);
+    }
+
+    #[test]
+    fn z_broken_lines() {
+        assert!(parse_z("/home|1700000000\n").is_err());
+        assert!(parse_z("/home|many|1700000000\n").is_err());
+        assert!(parse_z("/home|1|yesterday\n").is_err());
+        // Cut short while it was written
+        assert!(parse_z("/home|1|1700000000\n/tmp|1").is_err());
+    }
+
+    #[test]
+    fn autojump_weights_are_turned_back_into_visits() {
+        // One visit is 10, every next one is sqrt(weight^2 + 10^2)
+        let four_visits = (4.0f64 * 100.0).sqrt();
+        let content = format!("10.0\t/home/kostya\n{four_visits}\t/home/kostya/my work\n3.0\t/tmp\n");
+
+        let dirs = parse_autojump(&content, 1_700_000_000).unwrap();
+        assert_eq!(dirs.iter().map(|d| d.dir.as_str()).collect::<Vec<_>>(), ["/home/kostya", "/home/kostya/my work", "/tmp"]);
+        assert!((dirs[0].times - 1.0).abs() < 1e-9);
+        assert!((dirs[1].times - 4.0).abs() < 1e-9);
+        // Less than one visit is still a visit
+        assert_eq!(dirs[2].times, 1.0);
+        assert!(dirs.iter().all(|d| d.last_accessed_timestamp == 1_700_000_000));
+    }
+
+    #[test]
+    fn autojump_broken_lines() {
+        assert!(parse_autojump("/home/kostya\n", 0).is_err());
+        assert!(parse_autojump("ten\t/home/kostya\n", 0).is_err());
+    }
+}
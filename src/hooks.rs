@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::app_data;
+use crate::app_data::is_same_path;
+use crate::project_file;
+use crate::repos::to_full_path;
+use crate::shell::{is_valid_env_name, Shell};
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const TRUST_FILE_NAME: &str = "trusted_hooks.json";
+/// Keeps the value a hook variable had before entering the project, to restore it on leave
+const PREVIOUS_VALUE_PREFIX: &str = "WTS_PREV_";
+
+/// The [hooks] section of .wts.toml, e.g.
+/// ```toml
+/// [hooks]
+/// on_enter = ["& .venv/Scripts/Activate.ps1", "nvm use"]
+/// on_leave = ["deactivate"]
+/// env_file = ".env"
+/// env = { AWS_PROFILE = "payments" }
+/// ```
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Shell code that runs when entering the project
+    #[serde(default)]
+    on_enter: Vec<String>,
+    /// Shell code that runs when leaving the project
+    #[serde(default)]
+    on_leave: Vec<String>,
+    /// Variables that are set on enter and removed on leave
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// A dotenv file relative to the project root, loaded like env
+    env_file: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct TrustedHooks {
+    trusted: Vec<TrustedProject>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrustedProject {
+    path: String,
+    /// sha256 of the .wts.toml and its env_file that were trusted, any change requires trusting it again
+    hash: String,
+}
+
+/// Prints the leave hooks of the project we left and the enter hooks of the project we entered for the shell wrapper to run.
+/// Hooks run in the shell itself, so that the environment changes stay around.
+pub fn on_changed_directory(previous_dir: Option<&str>, new_dir: &str, shell: Shell) -> Result<()> {
+    let snippet = changed_directory_snippet(previous_dir, new_dir, shell);
+    if !snippet.is_empty() {
+        print!("<#Execute#>{}", snippet.join("\n"));
+    }
+
+    return Ok(());
+}
+
+fn changed_directory_snippet(previous_dir: Option<&str>, new_dir: &str, shell: Shell) -> Vec<String> {
+    let previous_root = previous_dir.and_then(|d| project_file::find_root(Path::new(d), false));
+    let new_root = project_file::find_root(Path::new(new_dir), false);
+
+    let is_same_root = match (&previous_root, &new_root) {
+        (Some(previous), Some(new)) => previous == new,
+        (None, None) => true,
+        _ => false,
+    };
+    if is_same_root {
+        return vec![];
+    }
+
+    let mut env = ShellEnv::new(shell);
+    if let Some(root) = previous_root {
+        if let Some(hooks) = load_trusted_hooks(&root) {
+            env.snippet.extend(hooks.on_leave.iter().cloned());
+            for key in hooks.env_variables(&root).keys() {
+                env.restore(key);
+            }
+        }
+    }
+
+    if let Some(root) = new_root {
+        if let Some(hooks) = load_trusted_hooks(&root) {
+            for (key, value) in hooks.env_variables(&root) {
+                env.replace(&key, &value);
+            }
+            env.snippet.extend(hooks.on_enter.iter().cloned());
+        }
+    }
+
+    return env.snippet;
+}
+
+/// The environment of the shell, as it will be after the snippet runs.
+/// wts inherits the environment of the shell, so the values from before the snippet are read from its own.
+struct ShellEnv {
+    shell: Shell,
+    changes: BTreeMap<String, Option<String>>,
+    snippet: Vec<String>,
+}
+
+impl ShellEnv {
+    fn new(shell: Shell) -> ShellEnv {
+        return ShellEnv { shell, changes: BTreeMap::new(), snippet: vec![] };
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        return match self.changes.get(key) {
+            Some(value) => value.clone(),
+            None => std::env::var(key).ok(),
+        };
+    }
+
+    fn set(&mut self, key: &str, value: Option<String>) {
+        self.snippet.push(match &value {
+            Some(value) => self.shell.set_env(key, value),
+            None => self.shell.unset_env(key),
+        });
+        self.changes.insert(key.to_string(), value);
+    }
+
+    /// Sets the variable of a hook, keeping the value the user had before.
+    fn replace(&mut self, key: &str, value: &str) {
+        let previous_key = format!("{PREVIOUS_VALUE_PREFIX}{key}");
+        // Already kept, e.g. in a shell started from inside of the project
+        if self.get(&previous_key).is_none() {
+            if let Some(previous) = self.get(key) {
+                self.set(&previous_key, Some(previous));
+            }
+        }
+        self.set(key, Some(value.to_string()));
+    }
+
+    /// Puts back the value from before the project was entered, or removes the variable if there was none.
+    fn restore(&mut self, key: &str) {
+        let previous_key = format!("{PREVIOUS_VALUE_PREFIX}{key}");
+        match self.get(&previous_key) {
+            Some(previous) => {
+                self.set(key, Some(previous));
+                self.set(&previous_key, None);
+            }
+            None => self.set(key, None),
+        }
+    }
+}
+
+pub fn trust(dir: &str) -> Result<()> {
+    let root = find_root_of(dir)?;
+    let hash = hash_project_file(Path::new(&root))?;
+
+    let mut trusted_hooks = app_data::read_json::<TrustedHooks>(TRUST_FILE_NAME)?;
+    trusted_hooks.trusted.retain(|t| !is_same_path(&t.path, &root));
+    trusted_hooks.trusted.push(TrustedProject { path: root, hash });
+    return app_data::write_json(TRUST_FILE_NAME, &trusted_hooks);
+}
+
+pub fn untrust(dir: &str) -> Result<()> {
+    let root = find_root_of(dir)?;
+
+    let mut trusted_hooks = app_data::read_json::<TrustedHooks>(TRUST_FILE_NAME)?;
+    trusted_hooks.trusted.retain(|t| !is_same_path(&t.path, &root));
+    return app_data::write_json(TRUST_FILE_NAME, &trusted_hooks);
+}
+
+impl Hooks {
+    fn env_variables(&self, root: &Path) -> BTreeMap<String, String> {
+        let mut variables = BTreeMap::new();
+        if let Some(env_file) = &self.env_file {
+            match std::fs::read_to_string(root.join(env_file)) {
+                Ok(content) => variables.extend(parse_env_file(&content)),
+                Err(e) => eprintln!("wts: could not read {env_file}: {e}"),
+            }
+        }
+        variables.extend(self.env.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        // The names end up in shell code as is, so anything unusual is dropped
+        variables.retain(|k, _| {
+            let is_valid = is_valid_env_name(k);
+            if !is_valid {
+                eprintln!("wts: skipping invalid environment variable name '{k}'");
+            }
+            is_valid
+        });
+        return variables;
+    }
+}
+
+fn find_root_of(dir: &str) -> Result<String> {
+    let full_path = to_full_path(&PathBuf::from(dir));
+    return match project_file::find_root(Path::new(&full_path), false) {
+        Some(root) => Ok(root.to_string_lossy().to_string()),
+        None => Err(format!("There is no {} in {full_path} or its parents", project_file::FILE_NAME).into()),
+    };
+}
+
+fn load_trusted_hooks(root: &Path) -> Option<Hooks> {
+    let hooks = match project_file::load(root) {
+        Ok(project_file) => project_file?.hooks?,
+        Err(e) => {
+            eprintln!("wts: {e}");
+            return None;
+        }
+    };
+
+    if !is_trusted(root) {
+        let root = root.display();
+        eprintln!("wts: hooks in {root} are not trusted, run `wts trust {root}` to allow them");
+        return None;
+    }
+
+    return Some(hooks);
+}
+
+fn is_trusted(root: &Path) -> bool {
+    let hash = match hash_project_file(root) {
+        Ok(hash) => hash,
+        Err(_) => return false,
+    };
+
+    let root = root.to_string_lossy();
+    let trusted_hooks = app_data::read_json::<TrustedHooks>(TRUST_FILE_NAME).unwrap_or_default();
+    return trusted_hooks.trusted.iter().any(|t| is_same_path(&t.path, &root) && t.hash == hash);
+}
+
+fn hash_project_file(root: &Path) -> Result<String> {
+    let path = root.join(project_file::FILE_NAME);
+    let content = std::fs::read(&path)
+        .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&content);
+
+    // The env file sets variables in the shell too, so it is trusted together with .wts.toml.
+    // Without an env_file the hash stays the one of .wts.toml alone, which keeps the older trusts valid.
+    let env_file = project_file::load(root).ok().flatten()
+        .and_then(|p| p.hooks)
+        .and_then(|h| h.env_file);
+    if let Some(env_file) = env_file {
+        hasher.update([0]);
+        if let Ok(env_content) = std::fs::read(root.join(&env_file)) {
+            hasher.update([1]);
+            hasher.update(&env_content);
+        }
+    }
+
+    let hash = hasher.finalize();
+    return Ok(hash.iter().map(|b| format!("{b:02x}")).collect());
+}
+
+fn parse_env_file(content: &str) -> Vec<(String, String)> {
+    return content.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            let line = l.strip_prefix("export ").unwrap_or(l);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let unquoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+            return Some((key.trim().to_string(), unquoted.to_string()));
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::empty_data_folder;
+
+    fn project(data_folder: &Path, wts_toml: &str) -> PathBuf {
+        let root = data_folder.join("project");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(project_file::FILE_NAME), wts_toml).unwrap();
+        return PathBuf::from(to_full_path(&root));
+    }
+
+    #[test]
+    fn changing_the_project_file_requires_trusting_again() {
+        let (_guard, data_folder) = empty_data_folder();
+        let root = project(&data_folder, "[hooks]\non_enter = [\"nvm use\"]\n");
+        assert!(!is_trusted(&root));
+
+        trust(root.to_str().unwrap()).unwrap();
+        assert!(is_trusted(&root));
+
+        std::fs::write(root.join(project_file::FILE_NAME), "[hooks]\non_enter = [\"rm -r ~\"]\n").unwrap();
+        assert!(!is_trusted(&root));
+    }
+
+    #[test]
+    fn changing_the_env_file_requires_trusting_again() {
+        let (_guard, data_folder) = empty_data_folder();
+        let root = project(&data_folder, "[hooks]\nenv_file = \".env\"\n");
+        std::fs::write(root.join(".env"), "AWS_PROFILE=payments\n").unwrap();
+
+        trust(root.to_str().unwrap()).unwrap();
+        assert!(is_trusted(&root));
+
+        std::fs::write(root.join(".env"), "AWS_PROFILE=production\n").unwrap();
+        assert!(!is_trusted(&root));
+
+        trust(root.to_str().unwrap()).unwrap();
+        std::fs::remove_file(root.join(".env")).unwrap();
+        assert!(!is_trusted(&root));
+    }
+
+    #[test]
+    fn leaving_restores_the_previous_values() {
+        let (_guard, data_folder) = empty_data_folder();
+        let root = project(&data_folder, "[hooks]\nenv = { WTS_TEST_PROFILE = \"payments\", WTS_TEST_REGION = \"eu\" }\n");
+        trust(root.to_str().unwrap()).unwrap();
+        let root = root.to_str().unwrap();
+        let outside = data_folder.to_str().unwrap();
+
+        std::env::set_var("WTS_TEST_PROFILE", "default");
+        std::env::remove_var("WTS_TEST_REGION");
+        assert_eq!(changed_directory_snippet(Some(outside), root, Shell::Bash), vec![
+            "export WTS_PREV_WTS_TEST_PROFILE='default'",
+            "export WTS_TEST_PROFILE='payments'",
+            "export WTS_TEST_REGION='eu'",
+        ]);
+
+        // As the shell has it after entering
+        std::env::set_var("WTS_PREV_WTS_TEST_PROFILE", "default");
+        std::env::set_var("WTS_TEST_PROFILE", "payments");
+        std::env::set_var("WTS_TEST_REGION", "eu");
+        assert_eq!(changed_directory_snippet(Some(root), outside, Shell::Bash), vec![
+            "export WTS_TEST_PROFILE='default'",
+            "unset WTS_PREV_WTS_TEST_PROFILE",
+            "unset WTS_TEST_REGION",
+        ]);
+
+        std::env::remove_var("WTS_PREV_WTS_TEST_PROFILE");
+        std::env::remove_var("WTS_TEST_PROFILE");
+        std::env::remove_var("WTS_TEST_REGION");
+    }
+
+    #[test]
+    fn moving_between_projects_keeps_the_value_from_outside() {
+        let (_guard, data_folder) = empty_data_folder();
+        let payments = project(&data_folder, "[hooks]\nenv = { WTS_TEST_ACCOUNT = \"payments\" }\n");
+        let billing = data_folder.join("billing");
+        std::fs::create_dir_all(&billing).unwrap();
+        std::fs::write(billing.join(project_file::FILE_NAME), "[hooks]\nenv = { WTS_TEST_ACCOUNT = \"billing\" }\n").unwrap();
+        trust(payments.to_str().unwrap()).unwrap();
+        trust(billing.to_str().unwrap()).unwrap();
+
+        std::env::set_var("WTS_PREV_WTS_TEST_ACCOUNT", "default");
+        std::env::set_var("WTS_TEST_ACCOUNT", "payments");
+        assert_eq!(changed_directory_snippet(payments.to_str(), billing.to_str().unwrap(), Shell::Bash), vec![
+            "export WTS_TEST_ACCOUNT='default'",
+            "unset WTS_PREV_WTS_TEST_ACCOUNT",
+            "export WTS_PREV_WTS_TEST_ACCOUNT='default'",
+            "export WTS_TEST_ACCOUNT='billing'",
+        ]);
+
+        std::env::remove_var("WTS_PREV_WTS_TEST_ACCOUNT");
+        std::env::remove_var("WTS_TEST_ACCOUNT");
+    }
+}
@@ -1,8 +1,11 @@
+# Every shell gets its own stack of directories for wcd -
+$env:WTS_SESSION = [guid]::NewGuid().ToString()
+
 $function:prompt = & {
 	$__last_prompt = $function:prompt
 	{ & $script:__last_prompt
 		$newDir = Get-Location
-		if ($newDir -ne $global:oldDir) {wts on-changed-directory $newDir}
+		if ($newDir -ne $global:oldDir) {wts on-changed-directory $newDir --previous "$global:oldDir"}
 		$global:oldDir = $newDir
 	}.GetNewClosure()
 }
@@ -10,3 +13,13 @@ $function:prompt = & {
 function wts { $result = @((wts.exe $args) -join "`n"); if ($result.StartsWith("<#Execute#>")) { @($result) | Invoke-Expression } else { echo $result } }
 
 function wcd { $result = @((wts.exe expand-cd $args) -join "`n"); if ($result.StartsWith("<#Execute#>")) { @($result) | Invoke-Expression } else { echo $result }  }
+
+wts.exe completions powershell | Out-String | Invoke-Expression
+
+Register-ArgumentCompleter -Native -CommandName wcd -ScriptBlock {
+	param($wordToComplete, $commandAst, $cursorPosition)
+	wts.exe complete-cd "$wordToComplete" | ForEach-Object {
+		$quoted = "'" + ($_ -replace "'", "''") + "'"
+		[System.Management.Automation.CompletionResult]::new($quoted, $_, 'ParameterValue', $_)
+	}
+}
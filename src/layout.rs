@@ -0,0 +1,237 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_data::is_same_path;
+use crate::config;
+use crate::project_file;
+use crate::shell::{quote_powershell, split_command};
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+/// Tabs and panes that are opened together for a project, e.g.
+/// ```toml
+/// [[layout.tabs]]
+/// title = "api"
+/// color = "#0078d4"
+///
+/// [[layout.tabs.panes]]
+/// command = "nvim ."
+///
+/// [[layout.tabs.panes]]
+/// split = "vertical"
+/// size = 0.4
+/// command = ["dotnet", "watch"]
+/// ```
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    #[serde(default)]
+    pub tabs: Vec<TabLayout>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TabLayout {
+    title: Option<String>,
+    color: Option<String>,
+    /// Windows Terminal profile for the panes that do not set their own
+    profile: Option<String>,
+    /// The first pane fills the tab, the rest split it
+    #[serde(default)]
+    panes: Vec<PaneLayout>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PaneLayout {
+    command: Option<PaneCommand>,
+    profile: Option<String>,
+    /// Relative to the project root
+    directory: Option<String>,
+    split: Option<SplitDirection>,
+    /// Part of the split pane taken by the new pane, between 0 and 1
+    size: Option<f32>,
+}
+
+/// A command line split on whitespace, or a list of arguments when they contain spaces.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum PaneCommand {
+    Line(String),
+    Args(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// The layout from the project .wts.toml, or from the global config.
+pub fn find_layout(project_dir: &str) -> Result<Option<Layout>> {
+    let project_layout = project_file::load(Path::new(project_dir))?.and_then(|f| f.layout);
+    if project_layout.is_some() {
+        return Ok(project_layout);
+    }
+
+    let config_layout = config::get().layouts.iter()
+        .find(|l| is_same_path(&l.path, project_dir))
+        .map(|l| l.layout.clone());
+    return Ok(config_layout);
+}
+
+/// A single powershell command line that opens all the tabs and panes of the layout with wt,
+/// where `wt` is the command line the subcommands are added to, e.g. `wt -w 0`.
+pub fn wt_command(layout: &Layout, project_dir: &str, wt: &str) -> String {
+    let mut subcommands = vec![];
+    for tab in &layout.tabs {
+        let default_pane = PaneLayout::default();
+        let panes = if tab.panes.is_empty() { std::slice::from_ref(&default_pane) } else { tab.panes.as_slice() };
+
+        for (idx, pane) in panes.iter().enumerate() {
+            let mut args: Vec<String> = vec![];
+            if idx == 0 {
+                args.push("nt".to_string());
+                if let Some(title) = &tab.title {
+                    args.extend(["--title".to_string(), quote(title)]);
+                }
+                if let Some(color) = &tab.color {
+                    args.extend(["--tabColor".to_string(), quote(color)]);
+                }
+            } else {
+                args.push("sp".to_string());
+                match pane.split {
+                    Some(SplitDirection::Horizontal) => args.push("-H".to_string()),
+                    Some(SplitDirection::Vertical) => args.push("-V".to_string()),
+                    None => {}
+                }
+                if let Some(size) = pane.size {
+                    args.extend(["-s".to_string(), size.to_string()]);
+                }
+            }
+
+            if let Some(profile) = pane.profile.as_ref().or(tab.profile.as_ref()) {
+                args.extend(["-p".to_string(), quote(profile)]);
+            }
+
+            // wt only runs on windows, so the separator is always a backslash
+            let directory = match &pane.directory {
+                Some(directory) => format!("{}\\{}", project_dir.trim_end_matches('\\'), directory),
+                None => project_dir.to_string(),
+            };
+            args.extend(["-d".to_string(), quote(&directory)]);
+
+            match &pane.command {
+                Some(PaneCommand::Line(line)) => args.extend(split_command(line).iter().map(|a| quote(a))),
+                Some(PaneCommand::Args(command_args)) => args.extend(command_args.iter().map(|a| quote(a))),
+                None => {}
+            }
+
+            subcommands.push(args.join(" "));
+        }
+    }
+
+    // A backtick keeps powershell from treating the semicolon as the end of the statement
+    return format!("{wt} {}", subcommands.join(" `; "));
+}
+
+fn quote(arg: &str) -> String {
+    // wt treats an unescaped semicolon as the next subcommand
+    return quote_powershell(&arg.replace(';', "\\;"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WT: &str = "wt -w 0";
+
+    fn parse(content: &str) -> Layout {
+        toml::from_str::<project_file::ProjectFile>(content).unwrap().layout.unwrap()
+    }
+
+    #[test]
+    fn single_tab_without_panes_opens_project_dir() {
+        let layout = parse("[[layout.tabs]]");
+
+        assert_eq!(wt_command(&layout, r"C:\work\api", WT), r"wt -w 0 nt -d 'C:\work\api'");
+    }
+
+    #[test]
+    fn tab_options_and_split_panes() {
+        let layout = parse(r##"
+            [[layout.tabs]]
+            title = "api"
+            color = "#0078d4"
+            profile = "PowerShell"
+
+            [[layout.tabs.panes]]
+            command = "nvim ."
+
+            [[layout.tabs.panes]]
+            split = "vertical"
+            size = 0.4
+            command = ["dotnet", "watch"]
+            directory = "src"
+
+            [[layout.tabs.panes]]
+            split = "horizontal"
+            profile = "Git Bash"
+        "##);
+
+        assert_eq!(
+            wt_command(&layout, r"C:\work\api", WT),
+            concat!(
+                r"wt -w 0 nt --title 'api' --tabColor '#0078d4' -p 'PowerShell' -d 'C:\work\api' 'nvim' '.'",
+                r" `; sp -V -s 0.4 -p 'PowerShell' -d 'C:\work\api\src' 'dotnet' 'watch'",
+                r" `; sp -H -p 'Git Bash' -d 'C:\work\api'",
+            )
+        );
+    }
+
+    #[test]
+    fn multiple_tabs_are_chained() {
+        let layout = parse(r#"
+            [[layout.tabs]]
+            title = "editor"
+
+            [[layout.tabs]]
+            title = "server"
+            panes = [{ command = "npm run dev" }]
+        "#);
+
+        assert_eq!(
+            wt_command(&layout, r"C:\web", WT),
+            r"wt -w 0 nt --title 'editor' -d 'C:\web' `; nt --title 'server' -d 'C:\web' 'npm' 'run' 'dev'"
+        );
+    }
+
+    #[test]
+    fn quotes_and_semicolons_are_escaped() {
+        let layout = parse(r#"
+            [[layout.tabs]]
+            title = "kostya's; api"
+            panes = [{ command = ["pwsh", "-Command", "echo 1; echo 2"] }]
+        "#);
+
+        assert_eq!(
+            wt_command(&layout, r"C:\it's", WT),
+            r"wt -w 0 nt --title 'kostya''s\; api' -d 'C:\it''s' 'pwsh' '-Command' 'echo 1\; echo 2'"
+        );
+    }
+
+    #[test]
+    fn command_line_keeps_quoted_arguments_together() {
+        let layout = parse(r#"
+            [[layout.tabs]]
+            panes = [{ command = "pwsh -NoExit -Command \"npm run dev\"" }]
+        "#);
+
+        assert_eq!(
+            wt_command(&layout, r"C:\web", WT),
+            r"wt -w 0 nt -d 'C:\web' 'pwsh' '-NoExit' '-Command' 'npm run dev'"
+        );
+    }
+}
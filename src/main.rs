@@ -1,10 +1,28 @@
 use std::{self};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 
+mod actions;
+mod app_data;
+mod config;
 mod dialogue;
+mod favorites;
+mod history;
+mod history_import;
+mod hooks;
+mod layout;
+mod picker;
+mod project_file;
+mod query;
 mod repos;
+mod session;
+mod shell;
+mod store;
+mod tags;
+mod visit_log;
 mod cd;
+#[cfg(test)]
+mod test_support;
 
 type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
 
@@ -17,14 +35,190 @@ struct Cli {
     // Make this command default
     #[clap(flatten)]
     find_project: FindProjectArgs,
+
+    /// A config file that overrides the settings from config.toml in the config folder
+    #[arg(long, global = true, env = "WTS_CONFIG")]
+    config: Option<String>,
+
+    /// Overrides a single setting, e.g. --set dialogue.height=20
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     FindProject(FindProjectArgs),
-    OnChangedDirectory { path: String },
-    ExpandCd { path: String },
+    OnChangedDirectory {
+        path: String,
+
+        /// The directory we came from, to know which project hooks to run
+        #[arg(long)]
+        previous: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = shell::Shell::Powershell)]
+        shell: shell::Shell,
+
+        /// Id of the shell session, to keep a stack of its directories
+        #[arg(long, env = "WTS_SESSION")]
+        session: Option<String>,
+    },
+    ExpandCd {
+        /// A path, keywords that match the segments of a visited path in order,
+        /// `-` and `-N` to go back to a previous directory of this shell,
+        /// or @keywords to only search inside of the current project
+        #[arg(required_unless_present_any = ["back", "here"], allow_hyphen_values = true)]
+        keywords: Vec<String>,
+
+        /// Choose among the previous directories of this shell
+        #[arg(long, conflicts_with_all = ["keywords", "here"])]
+        back: bool,
+
+        /// Only search inside of the git repository or project of the current directory
+        #[arg(long)]
+        here: bool,
+
+        #[arg(long, env = "WTS_SESSION")]
+        session: Option<String>,
+
+        #[arg(long, env = "WTS_PICKER")]
+        picker: Option<String>,
+    },
     Init {},
+    /// Pin a project so it is always listed first
+    Pin {
+        #[arg(default_value = ".")]
+        path: String,
+
+        /// Name that selects the project when typed in full
+        #[arg(short, long)]
+        alias: Option<String>,
+    },
+    /// Remove a pinned project by its path or alias
+    Unpin {
+        #[arg(default_value = ".")]
+        path_or_alias: String,
+    },
+    /// Allow the hooks from .wts.toml in this project to run, until the file changes
+    Trust {
+        #[arg(default_value = ".")]
+        dir: String,
+    },
+    /// Stop running the hooks from .wts.toml in this project
+    Untrust {
+        #[arg(default_value = ".")]
+        dir: String,
+    },
+    /// Print the projects found in the given folders without the interactive picker
+    List(ListArgs),
+    /// Print the best match for the terms among pinned projects and the wcd history
+    Query {
+        #[arg(required = true)]
+        terms: Vec<String>,
+
+        /// Print the top N matches with their scores instead
+        #[arg(short, long, num_args = 0..=1, default_missing_value = "10")]
+        list: Option<usize>,
+
+        /// Also search the projects found in this folder
+        #[arg(short, long)]
+        path: Option<String>,
+    },
+    /// Manage custom project tags, filter on them with #tag in the picker
+    Tag {
+        #[command(subcommand)]
+        command: TagCommands,
+    },
+    /// Print the script that completes wts commands in the shell
+    Completions {
+        #[arg(value_enum)]
+        shell: shell::Shell,
+    },
+    /// Print the wcd history directories that match the partial keywords, for completing wcd in the shell
+    #[command(hide = true)]
+    CompleteCd {
+        #[arg(allow_hyphen_values = true)]
+        partial: Vec<String>,
+    },
+    /// Inspect the settings
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Manage the directory history that wcd jumps to
+    History {
+        #[command(subcommand)]
+        command: HistoryCommands,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct ListArgs {
+    #[arg(default_value = ".")]
+    paths: Vec<String>,
+
+    #[arg(short, long, value_enum, default_value_t = repos::ListFormat::Json)]
+    format: repos::ListFormat,
+
+    /// Only list projects with this language, e.g. rust or csharp
+    #[arg(short, long = "lang")]
+    languages: Vec<String>,
+
+    /// Only list projects found in a folder which path contains this text
+    #[arg(short, long)]
+    root: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum TagCommands {
+    Add {
+        tag: String,
+        #[arg(default_value = ".")]
+        path: String,
+    },
+    Remove {
+        tag: String,
+        #[arg(default_value = ".")]
+        path: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the effective config, after all the layers and defaults are applied
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommands {
+    /// Add the directories from another tool, merging them with the ones already visited
+    Import {
+        #[arg(long = "from", value_enum)]
+        from: history_import::ImportSource,
+
+        /// The database to read, the default location of the tool when omitted
+        file: Option<String>,
+    },
+    /// Print the visited directories
+    List {
+        #[arg(long)]
+        json: bool,
+
+        #[arg(long, value_enum, default_value_t = history::SortOrder::Frecency)]
+        sort: history::SortOrder,
+    },
+    /// Record a visit to a directory
+    Add {
+        #[arg(default_value = ".")]
+        dir: String,
+    },
+    /// Forget a directory, or all the directories matching a glob like 'C:\temp\**'
+    Remove {
+        dir_or_pattern: String,
+    },
+    /// Forget the directories that do not exist anymore
+    Prune,
+    /// Forget all the directories
+    Clear,
 }
 
 #[derive(Debug, clap::Args)]
@@ -34,30 +228,116 @@ struct FindProjectArgs {
 
     #[arg(short, long)]
     new_tab: bool,
+
+    /// Command of an external picker like fzf to use instead of the built-in one
+    #[arg(long, env = "WTS_PICKER")]
+    picker: Option<String>,
 }
 
 fn main() -> Result<()> {
+    // WTS_TRACE=1 reports how long the command took, mostly to keep an eye on the prompt hook
+    let started = std::env::var_os("WTS_TRACE").map(|_| std::time::Instant::now());
+
+    let result = run();
+
+    if let Some(started) = started {
+        eprintln!("wts: took {:.2}ms", started.elapsed().as_secs_f64() * 1000.0);
+    }
+    return result;
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
+    if let Err(e) = config::init(cli.config, &cli.overrides) {
+        // The prompt hook and the completion run on their own all the time, they leave reporting a broken config
+        // to the commands that the user runs. They do nothing meanwhile, as the data folder is not known.
+        let runs_in_background = matches!(cli.command, Some(Commands::OnChangedDirectory { .. } | Commands::CompleteCd { .. }));
+        if runs_in_background {
+            return Ok(());
+        }
+        return Err(e);
+    }
 
     return match cli
         .command
         .unwrap_or(Commands::FindProject(cli.find_project))
     {
-        Commands::FindProject(FindProjectArgs { path, new_tab }) => {
-            return repos::find_project(path, new_tab);
+        Commands::FindProject(FindProjectArgs { path, new_tab, picker }) => {
+            return repos::find_project(path, new_tab, picker::Picker::from_arg(picker));
         }
-        Commands::OnChangedDirectory { path } => {
-            cd::on_changed_directory(&path)?;
+        Commands::OnChangedDirectory { path, previous, shell, session } => {
+            cd::on_changed_directory(&path, session.as_deref())?;
+            let previous = previous.filter(|p| !p.is_empty());
+            hooks::on_changed_directory(previous.as_deref(), &path, shell)?;
             return Ok(());
         },
-        Commands::ExpandCd { path } => {
-            return cd::expand(&path);
+        Commands::ExpandCd { keywords, back, here, session, picker } => {
+            let picker = picker::Picker::from_arg(picker);
+            if back {
+                return cd::pick_previous(session.as_deref(), picker);
+            }
+            if here {
+                return cd::expand_here(&keywords, picker);
+            }
+            return cd::expand(&keywords, session.as_deref(), picker);
         },
         Commands::Init {} => {
             let content = include_str!("init.ps1");
             print!("{:}", content);
             return Ok(());
         }
+        Commands::Pin { path, alias } => {
+            return favorites::pin(&path, alias);
+        }
+        Commands::Unpin { path_or_alias } => {
+            return favorites::unpin(&path_or_alias);
+        }
+        Commands::Trust { dir } => {
+            return hooks::trust(&dir);
+        }
+        Commands::Untrust { dir } => {
+            return hooks::untrust(&dir);
+        }
+        Commands::List(ListArgs { paths, format, languages, root }) => {
+            return repos::list_projects(paths, repos::ListOptions { format, languages, root });
+        }
+        Commands::Query { terms, list, path } => {
+            return query::query(terms, list, path);
+        }
+        Commands::Tag { command: TagCommands::Add { tag, path } } => {
+            return tags::add(&path, &tag);
+        }
+        Commands::Tag { command: TagCommands::Remove { tag, path } } => {
+            return tags::remove(&path, &tag);
+        }
+        Commands::Completions { shell } => {
+            clap_complete::generate(shell.completion_shell(), &mut Cli::command(), "wts", &mut std::io::stdout());
+            return Ok(());
+        }
+        Commands::CompleteCd { partial } => {
+            return cd::complete(&partial);
+        }
+        Commands::Config { command: ConfigCommands::Show } => {
+            return config::show();
+        }
+        Commands::History { command: HistoryCommands::Import { from, file } } => {
+            return history_import::import(from, file);
+        }
+        Commands::History { command: HistoryCommands::List { json, sort } } => {
+            return history::list(json, sort);
+        }
+        Commands::History { command: HistoryCommands::Add { dir } } => {
+            return history::add(&dir);
+        }
+        Commands::History { command: HistoryCommands::Remove { dir_or_pattern } } => {
+            return history::remove(&dir_or_pattern);
+        }
+        Commands::History { command: HistoryCommands::Prune } => {
+            return history::prune();
+        }
+        Commands::History { command: HistoryCommands::Clear } => {
+            return history::clear();
+        }
 
         _ => Ok(()),
     };
@@ -0,0 +1,121 @@
+use std::io;
+use std::io::{BufRead, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use dialoguer::console::Key;
+
+use crate::config;
+use crate::dialogue::dialogue_ui::{Dialogue, DialogueItem, DialogueMessage, Selection};
+
+/// Who shows the list of items to the user.
+pub enum Picker {
+    Internal,
+    /// A command like fzf, sk or tv, that reads items from stdin and prints the chosen one
+    External(String),
+}
+
+impl Picker {
+    /// The picker from --picker or WTS_PICKER, or from picker.command in the config.
+    pub fn from_arg(picker: Option<String>) -> Picker {
+        match picker.or_else(|| config::get().picker.command.clone()) {
+            None => Picker::Internal,
+            Some(picker) if picker.is_empty() || picker == "internal" => Picker::Internal,
+            Some(command) => Picker::External(command),
+        }
+    }
+}
+
+/// Shows the initial items together with the ones that arrive through the receiver, and returns the chosen one.
+/// External pickers always report Enter as the confirming key, and several chosen lines as the marked items
+/// (e.g. fzf --multi).
+pub fn pick<T>(picker: &Picker, receiver: Receiver<DialogueMessage<T>>, initial_items: Vec<T>, prompt: &str, confirm_keys: Vec<Key>, multi_select: bool)
+    -> io::Result<Option<Selection<T>>> where T: DialogueItem + Send + 'static {
+    match picker {
+        Picker::Internal => {
+            let mut dialogue = Dialogue::new(receiver);
+            dialogue.add_items(initial_items);
+            if let Some(height) = config::get().dialogue.height {
+                dialogue.height(height);
+            }
+            if multi_select {
+                dialogue.multi_select();
+            }
+            return dialogue.prompt(prompt).confirm_keys(confirm_keys).interact_selection();
+        }
+        Picker::External(command) => {
+            let mut items = pick_external(command, receiver, initial_items)?;
+            if items.is_empty() {
+                return Ok(None);
+            }
+
+            let marked = if multi_select && items.len() > 1 { items.clone() } else { vec![] };
+            return Ok(Some(Selection { item: items.remove(0), key: Key::Enter, marked }));
+        }
+    }
+}
+
+fn pick_external<T>(command: &str, receiver: Receiver<DialogueMessage<T>>, initial_items: Vec<T>) -> io::Result<Vec<T>>
+    where T: DialogueItem + Send + 'static {
+    let args = shell_words::split(command)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid picker command '{command}': {e}")))?;
+    let (program, args) = match args.split_first() {
+        Some(split) => split,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "The picker command is empty")),
+    };
+
+    // The picker draws its UI on the console itself, we only own its stdin and stdout
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("Could not start picker '{program}': {e}")))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let items = Arc::new(Mutex::new(Vec::<T>::new()));
+    let writer_items = items.clone();
+
+    // Items are streamed while they are being discovered, the picker sees the end of the input when the search finishes
+    thread::spawn(move || {
+        let mut write_items = |new_items: Vec<T>| -> io::Result<()> {
+            for item in new_items {
+                // Remembered before it is written, so that the chosen line can always be mapped back
+                let line = item.to_string();
+                writer_items.lock().unwrap().push(item);
+                writeln!(stdin, "{line}")?;
+            }
+            return stdin.flush();
+        };
+
+        if write_items(initial_items).is_err() {
+            return;
+        }
+
+        for message in receiver {
+            match message {
+                DialogueMessage::ItemsFound(new_items) => {
+                    // The picker is gone when its stdin is closed, nothing to stream anymore
+                    if write_items(new_items).is_err() {
+                        return;
+                    }
+                }
+                DialogueMessage::ProgressUpdate(_) => {}
+                DialogueMessage::Finish | DialogueMessage::ForceShutdown => return,
+            }
+        }
+    });
+
+    let stdout = io::BufReader::new(child.stdout.take().unwrap());
+    let selected_lines = stdout.lines().collect::<io::Result<Vec<_>>>()?;
+    child.wait()?;
+
+    let items = items.lock().unwrap();
+    return Ok(selected_lines.iter()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| items.iter().find(|i| i.to_string() == line).cloned())
+        .collect());
+}
@@ -0,0 +1,38 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::hooks::Hooks;
+use crate::layout::Layout;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+pub const FILE_NAME: &str = ".wts.toml";
+
+/// Per-project settings from the .wts.toml in the project root.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectFile {
+    pub layout: Option<Layout>,
+    pub hooks: Option<Hooks>,
+}
+
+/// The closest folder with a .wts.toml, starting from the directory itself.
+/// With `or_repository` the root of a git repository counts too, .git is a file in worktrees and submodules.
+pub fn find_root(dir: &Path, or_repository: bool) -> Option<PathBuf> {
+    return dir.ancestors()
+        .find(|d| d.join(FILE_NAME).is_file() || (or_repository && d.join(".git").exists()))
+        .map(|d| d.to_path_buf());
+}
+
+pub fn load(project_dir: &Path) -> Result<Option<ProjectFile>> {
+    let path = project_dir.join(FILE_NAME);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let project_file = toml::from_str::<ProjectFile>(&content)
+        .map_err(|e| format!("Invalid {}: {e}", path.display()))?;
+    return Ok(Some(project_file));
+}
@@ -0,0 +1,106 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+use crate::app_data::path_key;
+use crate::cd;
+use crate::dialogue::dialogue_ui::DialogueItem;
+use crate::dialogue::ranking;
+use crate::repos;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+/// Prints the best match for the terms among the known projects and the `wcd` history.
+pub fn query(terms: Vec<String>, list: Option<usize>, path: Option<String>) -> Result<()> {
+    let mut projects = repos::get_pinned_projects();
+    if let Some(path) = path {
+        projects.extend(repos::discover_projects(Path::new(&path)));
+    }
+
+    let mut candidates: Vec<Candidate> = vec![];
+    let mut seen_paths = HashSet::new();
+    let project_candidates = projects.iter().map(|p| Candidate::from_item(p.path(), p));
+    // The history is a nice to have here, a broken one should not stop the lookup
+    let history_candidates = cd::visited_dirs().unwrap_or_default().into_iter()
+        .map(|d| Candidate::from_item(&d, &d));
+    for candidate in project_candidates.chain(history_candidates) {
+        if seen_paths.insert(path_key(&candidate.path)) {
+            candidates.push(candidate);
+        }
+    }
+
+    let input = terms.join(" ");
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let predictions = ranking::rank(&candidates, &ranking::Query::parse(&input), &matcher, list.unwrap_or(1));
+
+    if predictions.is_empty() {
+        return Err(format!("No matches for '{input}'").into());
+    }
+
+    match list {
+        Some(_) => {
+            for prediction in predictions {
+                println!("{}\t{}", prediction.score, prediction.item.path);
+            }
+        }
+        None => println!("{}", predictions[0].item.path),
+    }
+
+    return Ok(());
+}
+
+/// A project or a history entry, scored the same way as in the picker.
+#[derive(Clone)]
+struct Candidate {
+    path: String,
+    display: String,
+    pinned: bool,
+    alias: Option<String>,
+    tags: Vec<String>,
+}
+
+impl Candidate {
+    fn from_item<T>(path: &str, item: &T) -> Candidate where T: DialogueItem {
+        return Candidate {
+            path: path.to_string(),
+            display: item.to_string(),
+            pinned: item.is_pinned(),
+            alias: item.alias().map(|a| a.to_string()),
+            tags: item.tags().iter().map(|t| t.to_string()).collect(),
+        };
+    }
+}
+
+impl PartialEq<Self> for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.path.eq(&other.path)
+    }
+}
+
+impl Eq for Candidate {}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.display)
+    }
+}
+
+impl DialogueItem for Candidate {
+    fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    fn tags(&self) -> Vec<&str> {
+        self.tags.iter().map(|t| t.as_str()).collect()
+    }
+
+    fn search_text(&self) -> String {
+        self.path.clone()
+    }
+}
@@ -1,27 +1,43 @@
 use mpsc::channel;
 use std::fmt::{Display, Formatter};
+use std::io::Write;
 use std::os::windows::prelude::*;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
 use std::{self, fs, thread};
 
+use dialoguer::console::Key;
 use path_absolutize::Absolutize;
+use serde::Serialize;
 
-use crate::dialogue::dialogue_ui::Dialogue;
+use crate::actions;
+use crate::app_data;
+use crate::actions::Action;
+use crate::config;
+use crate::dialogue::dialogue_ui::DialogueItem;
 use crate::dialogue::dialogue_ui::DialogueMessage;
 use crate::dialogue::dialogue_ui::DialogueMessage::{Finish, ForceShutdown};
 use crate::dialogue::dialogue_ui::{DialogueMessage::ItemsFound, DialogueMessage::ProgressUpdate};
+use crate::favorites;
+use crate::layout;
+use crate::picker;
+use crate::picker::Picker;
+use crate::tags;
+use crate::tags::ProjectTags;
 
 type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
 
-pub fn find_project(path: String, new_tab: bool) -> Result<()> {
+pub fn find_project(path: String, new_tab: bool, picker: Picker) -> Result<()> {
+    let pinned = get_pinned_projects();
+    let pinned_paths = pinned.iter().map(|p| p.path.clone()).collect::<Vec<_>>();
+
     let (tx, rx) = channel::<DialogueMessage<ProjectInfo>>();
     let search_sender = tx.clone();
     thread::spawn(move || {
         let path = Path::new(&path);
 
-        let mut updater = Updater::new(&search_sender);
+        let mut updater = Updater::new(&search_sender, pinned_paths);
         let repos = get_project_paths(path, &mut updater);
         search_sender.send(Finish).unwrap();
         return repos;
@@ -30,35 +46,162 @@ pub fn find_project(path: String, new_tab: bool) -> Result<()> {
     let ctrlc_sender = tx.clone();
     ctrlc::set_handler(move || ctrlc_sender.send(ForceShutdown).unwrap())?;
 
-    let mut selection = Dialogue::new(rx).prompt("Select repository").interact();
+    // Pinned projects are known upfront, so they are shown before the scan finds anything
+    let selection = picker::pick(&picker, rx, pinned, "Select repository", actions::confirm_keys(), true);
+
+    if let Ok(Some(selection)) = selection {
+        let requested = if new_tab { Some(Action::NewTab) } else { None };
+        let key = selection.key.clone();
+        open_projects(&selection.items(), &key, requested)?;
+    }
+
+    return Ok(());
+}
+
+pub fn list_projects(paths: Vec<String>, options: ListOptions) -> Result<()> {
+    let (tx, rx) = channel::<DialogueMessage<ProjectInfo>>();
+    thread::spawn(move || {
+        let mut updater = Updater::new(&tx, vec![]);
+        for path in paths {
+            get_project_paths(Path::new(&path), &mut updater);
+        }
+        tx.send(Finish).unwrap();
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    // Projects are printed as soon as they are found, so that pipelines can start working right away
+    for message in rx {
+        let projects = match message {
+            ItemsFound(projects) => projects,
+            Finish => break,
+            _ => continue,
+        };
 
-    if let Ok(Some(selected_repo)) = selection {
-        let selected = &selected_repo.path;
-        open_tab(selected, new_tab);
+        for project in projects.iter().filter(|p| options.matches(p)) {
+            match options.format {
+                ListFormat::Json => writeln!(stdout, "{}", serde_json::to_string(project)?)?,
+                ListFormat::Tsv => {
+                    let languages = project.detailed_repo_info.iter().map(|d| d.tag_name()).collect::<Vec<_>>();
+                    writeln!(stdout, "{}\t{}\t{}\t{}", project.path, languages.join(","), project.root, project.vcs.name())?
+                }
+                ListFormat::Nul => write!(stdout, "{}\0", project.path)?,
+            }
+        }
+        stdout.flush()?;
     }
 
     return Ok(());
 }
 
-fn path_to_project(p: &PathBuf) -> ProjectInfo {
+#[derive(Clone, Debug, clap::ValueEnum)]
+pub enum ListFormat {
+    /// One json object per line
+    Json,
+    /// Path, languages, root and vcs separated by tabs
+    Tsv,
+    /// Only paths, separated by NUL characters (for xargs -0)
+    Nul,
+}
+
+pub struct ListOptions {
+    pub format: ListFormat,
+    pub languages: Vec<String>,
+    pub root: Option<String>,
+}
+
+impl ListOptions {
+    fn matches(&self, project: &ProjectInfo) -> bool {
+        let matches_language = self.languages.is_empty() || project.detailed_repo_info.iter()
+            .any(|d| self.languages.iter().any(|l| l.eq_ignore_ascii_case(d.tag_name())));
+
+        let matches_root = match &self.root {
+            Some(root) => project.root.to_lowercase().contains(&root.to_lowercase()),
+            None => true,
+        };
+
+        return matches_language && matches_root;
+    }
+}
+
+fn path_to_project(p: &PathBuf, root: &str, project_tags: &ProjectTags) -> ProjectInfo {
     let details = get_repo_info(&p);
     let full_path = to_full_path(&p);
+    let vcs = if p.join(".git").exists() { VcsKind::Git } else { VcsKind::None };
     return ProjectInfo {
+        tags: project_tags.tags_for(&full_path),
         path: full_path,
         detailed_repo_info: details,
+        root: root.to_string(),
+        vcs,
+        pinned: false,
+        alias: None,
     };
 }
 
-fn open_tab(directory: &String, new_tab: bool) {
-    if new_tab {
-        print!("<#Execute#>wt -w 0 nt -d {:}", directory);
-    } else {
-        print!("<#Execute#>cd {:}", directory);
+/// Finds the projects in the folder without reporting the progress anywhere.
+pub(crate) fn discover_projects(path: &Path) -> Vec<ProjectInfo> {
+    let (tx, _rx) = channel::<DialogueMessage<ProjectInfo>>();
+    let mut updater = Updater::new(&tx, vec![]);
+    return get_project_paths(path, &mut updater);
+}
+
+pub(crate) fn get_pinned_projects() -> Vec<ProjectInfo> {
+    let project_tags = tags::load();
+    return favorites::load().pinned.into_iter()
+        .filter(|p| Path::new(&p.path).is_dir())
+        .map(|p| ProjectInfo {
+            pinned: true,
+            alias: p.alias,
+            // Pinned projects may live outside of any searched folder, so they are their own root
+            ..path_to_project(&PathBuf::from(&p.path), &p.path, &project_tags)
+        })
+        .collect();
+}
+
+/// Does the action for the key with every project. Several projects are opened in new tabs,
+/// unless the key asks for another action.
+fn open_projects(projects: &[ProjectInfo], key: &Key, requested: Option<Action>) -> Result<()> {
+    let Some(first) = projects.first() else {
+        return Ok(());
+    };
+
+    let settings = actions::load_settings();
+    let requested = if projects.len() > 1 { requested.or(Some(Action::NewTab)) } else { requested };
+    let action = match actions::action_for_key(key, requested, settings, &languages(first))? {
+        // The shell can only be in one of the directories
+        Some(Action::Cd) if projects.len() > 1 => Action::NewTab,
+        Some(action) => action,
+        None => return Ok(()),
+    };
+
+    if action == Action::CopyPath {
+        let paths = projects.iter().map(|p| p.path.as_str()).collect::<Vec<_>>();
+        return actions::run(action, &paths.join("\n"), settings, &languages(first));
     }
-    // std::process::Command::new("wt").args(["-w", "0", "nt", "-d", directory]).output().expect("failed to open new tab");
+
+    let mut commands = vec![];
+    for project in projects {
+        let command = match (action, layout::find_layout(&project.path)?) {
+            (Action::NewTab, Some(layout)) if !layout.tabs.is_empty() => {
+                Some(layout::wt_command(&layout, &project.path, &config::get().launcher.wt))
+            }
+            _ => actions::command(action, &project.path, settings, &languages(project))?,
+        };
+        commands.extend(command);
+    }
+
+    // The shell wrapper runs the lines one after another
+    if !commands.is_empty() {
+        print!("<#Execute#>{}", commands.join("\n"));
+    }
+    return Ok(());
+}
+
+fn languages(project: &ProjectInfo) -> Vec<&str> {
+    return project.detailed_repo_info.iter().map(|d| d.tag_name()).collect();
 }
 
-fn to_full_path(path: &PathBuf) -> String {
+pub(crate) fn to_full_path(path: &PathBuf) -> String {
     let expanded = shellexpand::full(path.to_str().unwrap())
         .unwrap()
         .into_owned();
@@ -103,6 +246,9 @@ fn is_valid_repository_marker(dir_entry: fs::DirEntry) -> Option<ProjectMarker>
 }
 
 fn get_project_paths(path: &std::path::Path, updater: &mut Updater) -> Vec<ProjectInfo> {
+    let project_tags = tags::load();
+    let skipped_folders = &config::get().discovery.skip;
+    let root = to_full_path(&PathBuf::from(path));
     let mut result = Vec::new();
     let mut traverse_queue = Vec::new();
     traverse_queue.push(PathBuf::from(path));
@@ -120,7 +266,8 @@ fn get_project_paths(path: &std::path::Path, updater: &mut Updater) -> Vec<Proje
 
                 let mut is_project = false;
                 for child in &children_dirs {
-                    if child.name == ".git" && child.is_dir {
+                    // .git is a file in worktrees and submodules
+                    if child.name == ".git" {
                         is_project = true;
                         break;
                     }
@@ -141,14 +288,14 @@ fn get_project_paths(path: &std::path::Path, updater: &mut Updater) -> Vec<Proje
                 }
 
                 if is_project {
-                    let repo = path_to_project(&popped);
+                    let repo = path_to_project(&popped, &root, &project_tags);
                     updater.on_new_project(&repo);
                     result.push(repo);
                     continue;
                 }
 
                 for child in &children_dirs {
-                    if child.name == "node_modules" && child.is_dir {
+                    if child.is_dir && skipped_folders.contains(&child.name) {
                         continue;
                     }
 
@@ -167,10 +314,45 @@ fn get_project_paths(path: &std::path::Path, updater: &mut Updater) -> Vec<Proje
     result
 }
 
-#[derive(Clone)]
-struct ProjectInfo {
+#[derive(Clone, Serialize)]
+pub(crate) struct ProjectInfo {
     path: String,
+    #[serde(rename = "languages")]
     detailed_repo_info: Vec<DetailedRepoInfo>,
+    /// The searched folder this project was found in
+    root: String,
+    vcs: VcsKind,
+    // Only known to the picker, `wts list` does not look at the favorites
+    #[serde(skip)]
+    pinned: bool,
+    #[serde(skip)]
+    alias: Option<String>,
+    tags: Vec<String>,
+}
+
+impl DialogueItem for ProjectInfo {
+    fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+
+    fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+
+    fn tags(&self) -> Vec<&str> {
+        let language_tags = self.detailed_repo_info.iter().map(|d| d.tag_name());
+        return language_tags.chain(self.tags.iter().map(|t| t.as_str())).collect();
+    }
+
+    fn search_text(&self) -> String {
+        self.path.clone()
+    }
+}
+
+impl ProjectInfo {
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
 }
 
 impl PartialEq<Self> for ProjectInfo {
@@ -184,28 +366,64 @@ impl Eq for ProjectInfo {}
 impl Display for ProjectInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let emoji = match self.detailed_repo_info.first() {
-            None => "",
-            Some(DetailedRepoInfo::NpmProject) => " [js]",
-            Some(DetailedRepoInfo::CsharpProject) => " [csharp]",
-            Some(DetailedRepoInfo::GoProject) => " [go]",
-            Some(DetailedRepoInfo::RustProject) => " [rust]",
-            Some(DetailedRepoInfo::LuaProject) => " [lua]",
+            None => "".to_string(),
+            Some(info) => format!(" [{}]", info.tag_name()),
+        };
+        let user_tags = self.tags.iter().map(|t| format!(" #{t}")).collect::<String>();
+
+        let pin = if self.pinned { "📌 " } else { "" };
+        let alias = match &self.alias {
+            Some(alias) => format!(" ({alias})"),
+            None => "".to_string(),
         };
 
-        let display = self.path.clone() + emoji;
+        let display = pin.to_string() + &self.path + &alias + &emoji + &user_tags;
         return f.write_str(&display);
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 enum DetailedRepoInfo {
+    #[serde(rename = "csharp")]
     CsharpProject,
+    #[serde(rename = "js")]
     NpmProject,
+    #[serde(rename = "go")]
     GoProject,
+    #[serde(rename = "rust")]
     RustProject,
+    #[serde(rename = "lua")]
     LuaProject,
 }
 
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum VcsKind {
+    Git,
+    None,
+}
+
+impl VcsKind {
+    fn name(&self) -> &'static str {
+        match self {
+            VcsKind::Git => "git",
+            VcsKind::None => "none",
+        }
+    }
+}
+
+impl DetailedRepoInfo {
+    fn tag_name(&self) -> &'static str {
+        match self {
+            DetailedRepoInfo::NpmProject => "js",
+            DetailedRepoInfo::CsharpProject => "csharp",
+            DetailedRepoInfo::GoProject => "go",
+            DetailedRepoInfo::RustProject => "rust",
+            DetailedRepoInfo::LuaProject => "lua",
+        }
+    }
+}
+
 fn get_repo_info(path: &PathBuf) -> Vec<DetailedRepoInfo> {
     let mut repos = Vec::new();
 
@@ -243,10 +461,16 @@ fn get_repo_info(path: &PathBuf) -> Vec<DetailedRepoInfo> {
 struct Updater<'a> {
     sender: &'a Sender<DialogueMessage<ProjectInfo>>,
     last_updated: Option<std::time::Instant>,
+    pinned_paths: Vec<String>,
 }
 
 impl<'a> Updater<'a> {
     pub(crate) fn on_new_project(&self, repo: &ProjectInfo) {
+        // Pinned projects are already in the list
+        if self.pinned_paths.iter().any(|p| app_data::is_same_path(p, &repo.path)) {
+            return;
+        }
+
         self.sender.send(ItemsFound(vec![repo.clone()])).unwrap()
     }
 
@@ -268,10 +492,11 @@ impl<'a> Updater<'a> {
         self.last_updated = Some(std::time::Instant::now());
     }
 
-    fn new(spinner: &Sender<DialogueMessage<ProjectInfo>>) -> Updater {
+    fn new(spinner: &Sender<DialogueMessage<ProjectInfo>>, pinned_paths: Vec<String>) -> Updater {
         Updater {
             sender: spinner,
             last_updated: None,
+            pinned_paths,
         }
     }
 }
@@ -0,0 +1,90 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::app_data;
+use crate::app_data::is_same_path;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const FOLDER_NAME: &str = "sessions";
+
+/// Past this size the stack is cut down to the latest directories.
+const MAX_STACK_SIZE: u64 = 16 * 1024;
+const KEPT_DIRS: usize = 100;
+
+/// Stacks of sessions that were not used for this long belong to closed shells.
+const MAX_SESSION_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Remembers the directory as the latest one of the shell session, one line per directory change.
+pub fn push(session: &str, dir: &str) -> Result<()> {
+    let path = stack_path(session)?;
+    if !path.exists() {
+        std::fs::create_dir_all(sessions_folder())?;
+        remove_old_sessions();
+    }
+
+    let mut file = OpenOptions::new().append(true).create(true).open(&path)?;
+    file.write_all(format!("{dir}\n").as_bytes())?;
+
+    if file.metadata()?.len() > MAX_STACK_SIZE {
+        let content = std::fs::read_to_string(&path)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        let kept = &lines[lines.len().saturating_sub(KEPT_DIRS)..];
+        std::fs::write(&path, format!("{}\n", kept.join("\n")))?;
+    }
+
+    return Ok(());
+}
+
+/// Directories this session was in before the current one, the latest first and each only once.
+pub fn previous_dirs(session: &str, current_dir: &str) -> Result<Vec<String>> {
+    let path = stack_path(session)?;
+    let content = match std::fs::read_to_string(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        content => content?,
+    };
+
+    let mut dirs: Vec<String> = vec![];
+    for dir in content.lines().rev() {
+        if is_same_path(dir, current_dir) || dirs.iter().any(|d| is_same_path(d, dir)) {
+            continue;
+        }
+        dirs.push(dir.to_string());
+    }
+
+    return Ok(dirs);
+}
+
+fn stack_path(session: &str) -> Result<PathBuf> {
+    // The id ends up in a file name
+    if session.is_empty() || !session.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("Invalid session id '{session}'").into());
+    }
+
+    return Ok(sessions_folder().join(format!("{session}.log")));
+}
+
+fn sessions_folder() -> PathBuf {
+    return app_data::app_folder().join(FOLDER_NAME);
+}
+
+fn remove_old_sessions() {
+    let Ok(entries) = std::fs::read_dir(sessions_folder()) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let is_old = entry.metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age > MAX_SESSION_AGE);
+
+        // Another shell may be cleaning up at the same time, failing to remove is fine
+        if is_old {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
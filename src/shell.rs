@@ -0,0 +1,94 @@
+/// Shells that the snippets printed for the shell wrapper are written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Powershell,
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn completion_shell(&self) -> clap_complete::Shell {
+        match self {
+            Shell::Powershell => clap_complete::Shell::PowerShell,
+            Shell::Bash => clap_complete::Shell::Bash,
+            Shell::Zsh => clap_complete::Shell::Zsh,
+            Shell::Fish => clap_complete::Shell::Fish,
+        }
+    }
+
+    pub fn set_env(&self, key: &str, value: &str) -> String {
+        match self {
+            Shell::Powershell => format!("$env:{key} = {}", self.quote(value)),
+            Shell::Bash | Shell::Zsh => format!("export {key}={}", self.quote(value)),
+            Shell::Fish => format!("set -gx {key} {}", self.quote(value)),
+        }
+    }
+
+    pub fn unset_env(&self, key: &str) -> String {
+        match self {
+            Shell::Powershell => format!("Remove-Item Env:{key} -ErrorAction SilentlyContinue"),
+            Shell::Bash | Shell::Zsh => format!("unset {key}"),
+            Shell::Fish => format!("set -e {key}"),
+        }
+    }
+
+    /// Quotes the value so that the shell does not expand anything inside of it.
+    pub fn quote(&self, value: &str) -> String {
+        match self {
+            Shell::Powershell => quote_powershell(value),
+            Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', "'\\''")),
+            Shell::Fish => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+        }
+    }
+}
+
+pub fn is_valid_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    return chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+}
+
+/// Single quotes, so that powershell does not expand anything inside of the value.
+pub fn quote_powershell(value: &str) -> String {
+    return format!("'{}'", value.replace('\'', "''"));
+}
+
+/// Splits at spaces outside of quotes. Unlike in a posix shell backslashes are kept, as they separate windows paths.
+pub fn split_command(command: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut part: Option<String> = None;
+    let mut quote_char = None;
+    for c in command.chars() {
+        match (quote_char, c) {
+            (Some(q), c) if c == q => quote_char = None,
+            (Some(_), c) => part.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote_char = Some(c);
+                // Quotes around nothing are still an (empty) argument
+                part.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => parts.extend(part.take()),
+            (None, c) => part.get_or_insert_with(String::new).push(c),
+        }
+    }
+    parts.extend(part);
+    return parts;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_commands_at_unquoted_spaces() {
+        assert_eq!(split_command("code"), vec!["code"]);
+        assert_eq!(split_command("  code   -n "), vec!["code", "-n"]);
+        assert_eq!(split_command(r"C:\tools\vim.exe -p"), vec![r"C:\tools\vim.exe", "-p"]);
+        assert_eq!(split_command(r#""C:\Program Files\Microsoft VS Code\Code.exe" -n"#),
+                   vec![r"C:\Program Files\Microsoft VS Code\Code.exe", "-n"]);
+        assert_eq!(split_command(r#"'C:\Program Files\x.exe' --title "it's mine""#),
+                   vec![r"C:\Program Files\x.exe", "--title", "it's mine"]);
+        assert_eq!(split_command(r#"vim """#), vec!["vim", ""]);
+    }
+}
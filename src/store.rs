@@ -0,0 +1,153 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::app_data;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+/// A json file in the app folder that is shared by all the open shells.
+/// Access goes through a lock file, writes replace the file atomically and keep the previous version as a backup.
+pub struct LockedStore {
+    path: PathBuf,
+    main_is_valid: bool,
+    // Closing the file releases the lock
+    lock: File,
+}
+
+impl LockedStore {
+    /// For reading and then writing the data, other processes wait until this one is dropped.
+    pub fn lock_exclusive(file_name: &str) -> Result<LockedStore> {
+        let store = LockedStore::open(file_name)?;
+        store.lock.lock()?;
+        return Ok(store);
+    }
+
+    /// For reading only, does not block other readers.
+    pub fn lock_shared(file_name: &str) -> Result<LockedStore> {
+        let store = LockedStore::open(file_name)?;
+        store.lock.lock_shared()?;
+        return Ok(store);
+    }
+
+    fn open(file_name: &str) -> Result<LockedStore> {
+        let app_folder = app_data::app_folder();
+        if !app_folder.exists() {
+            std::fs::create_dir_all(&app_folder)?;
+        }
+
+        let lock = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(app_folder.join(format!("{file_name}.lock")))?;
+
+        return Ok(LockedStore {
+            path: app_folder.join(file_name),
+            main_is_valid: false,
+            lock,
+        });
+    }
+
+    /// Reads the data, falling back to the backup when the file is broken and to the default value when there is no file yet.
+    /// A broken file without a valid backup is moved aside, so that the data starts over instead of failing every time.
+    pub fn read<T>(&mut self) -> Result<T> where T: DeserializeOwned + Default {
+        if !self.path.exists() {
+            return Ok(T::default());
+        }
+
+        // A file that can't be read is an error, only one that does not parse is broken
+        let error = match read_file::<T>(&self.path)? {
+            Ok(data) => {
+                self.main_is_valid = true;
+                return Ok(data);
+            }
+            Err(e) => e,
+        };
+
+        let backup_path = self.backup_path();
+        if backup_path.exists() {
+            if let Ok(Ok(data)) = read_file::<T>(&backup_path) {
+                eprintln!("wts: {} is broken ({error}), using the backup", self.path.display());
+                return Ok(data);
+            }
+        }
+
+        let broken_path = self.path.with_extension("json.broken");
+        match std::fs::rename(&self.path, &broken_path) {
+            Ok(()) => eprintln!("wts: {} is broken and there is no valid backup ({error}), moved it to {} and starting over",
+                                self.path.display(), broken_path.display()),
+            // Another reader moved it already
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        return Ok(T::default());
+    }
+
+    pub fn write<T>(&self, data: &T) -> Result<()> where T: Serialize {
+        let temp_path = self.path.with_extension("json.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            serde_json::to_writer(&mut writer, data)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+
+        // A broken file is not worth keeping, the backup it failed to replace is better
+        if self.main_is_valid {
+            std::fs::copy(&self.path, self.backup_path())?;
+        }
+
+        std::fs::rename(&temp_path, &self.path)?;
+        return Ok(());
+    }
+
+    fn backup_path(&self) -> PathBuf {
+        return self.path.with_extension("json.bak");
+    }
+}
+
+fn read_file<T>(path: &PathBuf) -> Result<serde_json::Result<T>> where T: DeserializeOwned {
+    let file = File::open(path)?;
+    return Ok(serde_json::from_reader::<_, T>(BufReader::new(file)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::empty_data_folder;
+
+    const FILE_NAME: &str = "store_test.json";
+
+    #[test]
+    fn broken_file_without_backup_starts_over() {
+        let (_guard, data_folder) = empty_data_folder();
+        std::fs::write(data_folder.join(FILE_NAME), "[1, 2").unwrap();
+
+        let mut store = LockedStore::lock_exclusive(FILE_NAME).unwrap();
+        assert_eq!(store.read::<Vec<u32>>().unwrap(), Vec::<u32>::new());
+        assert_eq!(std::fs::read_to_string(data_folder.join("store_test.json.broken")).unwrap(), "[1, 2");
+
+        store.write(&vec![3]).unwrap();
+        drop(store);
+        assert_eq!(LockedStore::lock_shared(FILE_NAME).unwrap().read::<Vec<u32>>().unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn broken_file_falls_back_to_the_backup() {
+        let (_guard, data_folder) = empty_data_folder();
+        let mut store = LockedStore::lock_exclusive(FILE_NAME).unwrap();
+        store.write(&vec![1]).unwrap();
+        store.read::<Vec<u32>>().unwrap();
+        store.write(&vec![1, 2]).unwrap();
+        drop(store);
+
+        std::fs::write(data_folder.join(FILE_NAME), "[1, 2").unwrap();
+        assert_eq!(LockedStore::lock_shared(FILE_NAME).unwrap().read::<Vec<u32>>().unwrap(), vec![1]);
+        assert!(!data_folder.join("store_test.json.broken").exists());
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::app_data;
+use crate::app_data::is_same_path;
+use crate::repos::to_full_path;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const FILE_NAME: &str = "project_tags.json";
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ProjectTags {
+    projects: Vec<TaggedProject>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TaggedProject {
+    path: String,
+    tags: Vec<String>,
+}
+
+impl ProjectTags {
+    pub fn tags_for(&self, path: &str) -> Vec<String> {
+        return self.projects.iter()
+            .find(|p| is_same_path(&p.path, path))
+            .map(|p| p.tags.clone())
+            .unwrap_or_default();
+    }
+}
+
+pub fn load() -> ProjectTags {
+    // A broken tags file should not prevent the picker from opening
+    return app_data::read_json::<ProjectTags>(FILE_NAME).unwrap_or_default();
+}
+
+pub fn add(path: &str, tag: &str) -> Result<()> {
+    let tag = normalize_tag(tag)?;
+    let full_path = to_full_path(&PathBuf::from(path));
+    let mut project_tags = app_data::read_json::<ProjectTags>(FILE_NAME)?;
+
+    match project_tags.projects.iter_mut().find(|p| is_same_path(&p.path, &full_path)) {
+        Some(project) => {
+            if !project.tags.contains(&tag) {
+                project.tags.push(tag);
+            }
+        }
+        None => {
+            project_tags.projects.push(TaggedProject {
+                path: full_path,
+                tags: vec![tag],
+            });
+        }
+    }
+
+    return app_data::write_json(FILE_NAME, &project_tags);
+}
+
+pub fn remove(path: &str, tag: &str) -> Result<()> {
+    let tag = normalize_tag(tag)?;
+    let full_path = to_full_path(&PathBuf::from(path));
+    let mut project_tags = app_data::read_json::<ProjectTags>(FILE_NAME)?;
+
+    let project = project_tags.projects.iter_mut()
+        .find(|p| is_same_path(&p.path, &full_path) && p.tags.contains(&tag));
+    match project {
+        Some(project) => project.tags.retain(|t| t != &tag),
+        None => return Err(format!("{full_path} is not tagged with '{tag}'").into()),
+    }
+
+    project_tags.projects.retain(|p| !p.tags.is_empty());
+    return app_data::write_json(FILE_NAME, &project_tags);
+}
+
+fn normalize_tag(tag: &str) -> Result<String> {
+    // Tags are typed as #tag in the picker, so accept them with the prefix too
+    let tag = tag.trim().trim_start_matches('#').to_lowercase();
+    if tag.is_empty() || tag.contains(char::is_whitespace) {
+        return Err(format!("'{tag}' is not a valid tag, tags can not be empty or contain spaces").into());
+    }
+
+    return Ok(tag);
+}
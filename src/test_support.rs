@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::config::{self, Config, HistoryConfig};
+
+static DATA_FOLDER_LOCK: Mutex<()> = Mutex::new(());
+
+/// The config that `config::get` returns in tests: the defaults, without the config.toml of the user,
+/// and the data in a temporary folder.
+pub fn config() -> Config {
+    return Config {
+        data_folder: Some(std::env::temp_dir().join(format!("wts-test-{}", std::process::id()))),
+        // The temp folder is excluded from the history by default
+        history: HistoryConfig { exclude: vec![], ..HistoryConfig::default() },
+        ..Config::default()
+    };
+}
+
+/// Empties the data folder of the test process.
+/// The folder is shared by the tests, the guard keeps them from using it at the same time.
+pub fn empty_data_folder() -> (MutexGuard<'static, ()>, PathBuf) {
+    let guard = DATA_FOLDER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let folder = config::get().data_folder.clone().unwrap();
+
+    let _ = std::fs::remove_dir_all(&folder);
+    std::fs::create_dir_all(&folder).unwrap();
+    return (guard, folder);
+}
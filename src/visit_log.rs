@@ -0,0 +1,113 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use crate::app_data;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+const FILE_NAME: &str = "directory_visits.log";
+const COMPACTING_FILE_NAME: &str = "directory_visits.compacting.log";
+
+/// Past this size the prompt hook folds the log into the history itself.
+const MAX_LOG_SIZE: u64 = 64 * 1024;
+
+pub struct Visit {
+    pub timestamp: u64,
+    pub dir: String,
+}
+
+pub struct Compaction {
+    pub visits: Vec<Visit>,
+    /// sha256 of the log that is being folded, saved together with the history that has its visits
+    pub log_hash: Option<String>,
+}
+
+/// Appends a single line, so that the prompt hook does not depend on the size of the history.
+pub fn append(timestamp: u64, dir: &str) -> Result<()> {
+    let line = format!("{timestamp}\t{dir}\n");
+    let open = || OpenOptions::new().append(true).create(true).open(log_path());
+
+    let mut file = match open() {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            std::fs::create_dir_all(app_data::app_folder())?;
+            open()?
+        }
+        file => file?,
+    };
+
+    // A single write keeps lines from concurrent shells from interleaving
+    file.write_all(line.as_bytes())?;
+    return Ok(());
+}
+
+pub fn is_over_limit() -> bool {
+    return std::fs::metadata(log_path()).is_ok_and(|m| m.len() > MAX_LOG_SIZE);
+}
+
+pub fn has_visits() -> bool {
+    return std::fs::metadata(log_path()).is_ok_and(|m| m.len() > 0) || compacting_path().exists();
+}
+
+/// Moves the log aside and returns its visits, shells keep appending to a new log meanwhile.
+/// Should be called under the history lock, and followed by `finish_compaction` once the visits are saved.
+/// `compacted_hash` is the `log_hash` saved with the history, it tells whether a leftover log was saved already.
+pub fn start_compaction(compacted_hash: Option<&str>) -> Result<Compaction> {
+    let compacting_path = compacting_path();
+
+    // A leftover from a compaction that did not finish is processed first, the current log waits for the next time
+    if compacting_path.exists() {
+        let content = std::fs::read(&compacting_path)?;
+        let log_hash = hash(&content);
+        if compacted_hash != Some(log_hash.as_str()) {
+            return Ok(Compaction { visits: parse(&content), log_hash: Some(log_hash) });
+        }
+
+        // The history was saved with these visits before the log could be removed, they must not be counted twice
+        finish_compaction()?;
+    }
+
+    match std::fs::rename(log_path(), &compacting_path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Compaction { visits: vec![], log_hash: None }),
+        result => result?,
+    }
+
+    let content = std::fs::read(&compacting_path)?;
+    return Ok(Compaction { visits: parse(&content), log_hash: Some(hash(&content)) });
+}
+
+fn parse(content: &[u8]) -> Vec<Visit> {
+    let content = String::from_utf8_lossy(content);
+    return content.lines()
+        .filter_map(|line| {
+            // A line cut short by a crash is skipped
+            let (timestamp, dir) = line.split_once('\t')?;
+            Some(Visit {
+                timestamp: timestamp.parse().ok()?,
+                dir: dir.to_string(),
+            })
+        })
+        .collect();
+}
+
+fn hash(content: &[u8]) -> String {
+    // Every line has a timestamp, so two logs are never the same
+    return Sha256::digest(content).iter().map(|b| format!("{b:02x}")).collect();
+}
+
+pub fn finish_compaction() -> Result<()> {
+    match std::fs::remove_file(compacting_path()) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+fn log_path() -> PathBuf {
+    return app_data::app_folder().join(FILE_NAME);
+}
+
+fn compacting_path() -> PathBuf {
+    return app_data::app_folder().join(COMPACTING_FILE_NAME);
+}